@@ -0,0 +1,37 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use aoc2023_day05::generate::{generate, GeneratorConfig};
+use aoc2023_day05::{Almanac, ParseOptions};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// Writes a generated almanac of about 100 MB, in the text format, and returns its path.
+fn write_large_input() -> String {
+    let path = format!("{}/large_almanac.txt", env!("CARGO_TARGET_TMPDIR"));
+    let config = GeneratorConfig { stages: 7, rules_per_stage: 550_000, ..GeneratorConfig::default() };
+    std::fs::write(&path, generate(&config).to_string()).unwrap();
+    path
+}
+
+fn bench_parse_large_input(c: &mut Criterion) {
+    let path = write_large_input();
+    let options = ParseOptions::default();
+    assert_eq!(
+        Almanac::from_reader_with_options(BufReader::new(File::open(&path).unwrap()), &options).unwrap(),
+        Almanac::from_file_mmap(&File::open(&path).unwrap(), &options).unwrap()
+    );
+
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(std::fs::metadata(&path).unwrap().len()));
+    group.bench_function("reader", |b| {
+        b.iter(|| Almanac::from_reader_with_options(BufReader::new(File::open(&path).unwrap()), &options).unwrap())
+    });
+    group.bench_function("mmap", |b| {
+        b.iter(|| Almanac::from_file_mmap(&File::open(&path).unwrap(), &options).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse_large_input);
+criterion_main!(benches);
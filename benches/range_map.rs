@@ -0,0 +1,101 @@
+use std::hint::black_box;
+
+use aoc2023_day05::generate::{generate, GeneratorConfig};
+use aoc2023_day05::Resolver;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+fn bench_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RangeMap::get");
+    for rules in [10, 100, 1_000, 10_000] {
+        let almanac = generate(&GeneratorConfig { stages: 1, rules_per_stage: rules, ..GeneratorConfig::default() });
+        let (_, range_map) = &almanac.chain.range_maps()[0];
+        group.bench_with_input(BenchmarkId::from_parameter(rules), range_map, |b, range_map| {
+            let mut value = 0u64;
+            b.iter(|| {
+                value = value.wrapping_add(2_654_435_761) % (1 << 32);
+                range_map.get(black_box(value))
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_get_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RangeMap::get_many");
+    group.sample_size(10);
+    let mut value = 0u64;
+    let scattered: Vec<u64> = (0..1_000_000)
+        .map(|_| {
+            value = value.wrapping_add(2_654_435_761) % (1 << 32);
+            value
+        })
+        .collect();
+    let mut sorted = scattered.clone();
+    sorted.sort_unstable();
+    for rules in [10_000, 1_000_000] {
+        let almanac = generate(&GeneratorConfig { stages: 1, rules_per_stage: rules, ..GeneratorConfig::default() });
+        let (_, range_map) = &almanac.chain.range_maps()[0];
+        for (order, values) in [("scattered", &scattered), ("sorted", &sorted)] {
+            let get = BenchmarkId::new(format!("get/{}", order), rules);
+            group.bench_with_input(get, values, |b, values| {
+                b.iter(|| values.iter().map(|&value| range_map.get(value)).collect::<Vec<_>>())
+            });
+            let get_many = BenchmarkId::new(format!("get_many/{}", order), rules);
+            group.bench_with_input(get_many, values, |b, values| b.iter(|| range_map.get_many(black_box(values))));
+        }
+    }
+    group.finish();
+}
+
+fn bench_get_ranges(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RangeMap::get_ranges");
+    for rules in [10, 100, 1_000, 100_000] {
+        let almanac = generate(&GeneratorConfig { stages: 1, rules_per_stage: rules, ..GeneratorConfig::default() });
+        let (_, range_map) = &almanac.chain.range_maps()[0];
+        group.bench_with_input(BenchmarkId::from_parameter(rules), range_map, |b, range_map| {
+            b.iter(|| range_map.get_ranges(black_box(1 << 30), black_box(1 << 31)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_resolve_ranges(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RangeMapChain::resolve_ranges");
+    for rules in [10, 40, 200] {
+        let almanac = generate(&GeneratorConfig { rules_per_stage: rules, ..GeneratorConfig::default() });
+        let seed_ranges = almanac.seed_ranges().unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(rules), &almanac.chain, |b, chain| {
+            b.iter(|| chain.resolve_ranges(black_box(&seed_ranges), "location"))
+        });
+        let with_resolver = BenchmarkId::new("resolver", rules);
+        group.bench_with_input(with_resolver, &almanac.chain, |b, chain| {
+            let mut resolver = Resolver::new();
+            b.iter(|| chain.resolve_ranges_with(black_box(&seed_ranges), "location", &mut resolver).map(<[_]>::len))
+        });
+    }
+    group.finish();
+}
+
+fn bench_resolve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RangeMapChain::resolve");
+    let almanac = generate(&GeneratorConfig::default());
+    let location = almanac.chain.stage_id("location").unwrap();
+    group.bench_function("label", |b| {
+        let mut value = 0u64;
+        b.iter(|| {
+            value = value.wrapping_add(2_654_435_761) % (1 << 32);
+            almanac.chain.resolve(black_box(value), "location")
+        })
+    });
+    group.bench_function("stage_id", |b| {
+        let mut value = 0u64;
+        b.iter(|| {
+            value = value.wrapping_add(2_654_435_761) % (1 << 32);
+            almanac.chain.resolve_to(black_box(value), location)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_get, bench_get_many, bench_get_ranges, bench_resolve_ranges, bench_resolve);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+//! Feeds arbitrary input to the almanac parsers. Errors are fine, panics are not. Run with
+//! `cargo +nightly fuzz run parse_almanac`, seeding the corpus with `tests/data` if you like.
+
+#![no_main]
+
+use aoc2023_day05::{Almanac, ParseOptions};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = Almanac::parse(text);
+    }
+    // Invalid UTF-8 must come back as an error from the reader, too.
+    let _ = Almanac::from_reader(data);
+    let _ = Almanac::from_reader_with_options(data, &ParseOptions { strict: true, merge_duplicate_sections: true });
+    let _ = Almanac::from_json_reader(data);
+});
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::io::{BufRead, Read};
+use std::sync::Mutex;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::parse::{parse, parse_bytes, parse_json, parse_with_options};
+use crate::{merge_ranges, OddSeedCount, ParseError, ParseOptions, RangeMapChain, VerifyError};
+
+/// A parsed almanac: the numbers of the seeds line and the chain of maps that follows it.
+/// Locations are the values of the category the last map of the chain resolves to, `location`
+/// for a puzzle input.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Almanac {
+    pub seeds: Vec<u64>,
+    pub chain: RangeMapChain,
+}
+
+impl Almanac {
+
+    /// Parses the full text of an almanac.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        Self::from_reader(text.as_bytes())
+    }
+
+    /// Like `parse`, but rejects sections whose rules have overlapping source ranges.
+    pub fn parse_strict(text: &str) -> Result<Self, ParseError> {
+        Self::from_reader_strict(text.as_bytes())
+    }
+
+    /// Parses an almanac line by line from any buffered reader.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, ParseError> {
+        let (seeds, chain) = parse(reader, false)?;
+        Ok(Self { seeds, chain })
+    }
+
+    /// Like `from_reader`, but rejects sections whose rules have overlapping source ranges.
+    pub fn from_reader_strict<R: BufRead>(reader: R) -> Result<Self, ParseError> {
+        let (seeds, chain) = parse(reader, true)?;
+        Ok(Self { seeds, chain })
+    }
+
+    /// Like `from_reader`, reading the text format as set by `options`.
+    pub fn from_reader_with_options<R: BufRead>(reader: R, options: &ParseOptions) -> Result<Self, ParseError> {
+        let (seeds, chain) = parse_with_options(reader, options)?;
+        Ok(Self { seeds, chain })
+    }
+
+    /// Like `from_reader_with_options`, parsing the whole text at once from `bytes` instead of a
+    /// line at a time. Faster for large inputs already in memory, with the same results.
+    pub fn from_bytes_with_options(bytes: &[u8], options: &ParseOptions) -> Result<Self, ParseError> {
+        let (seeds, chain) = parse_bytes(bytes, options)?;
+        Ok(Self { seeds, chain })
+    }
+
+    /// Like `from_bytes_with_options`, parsing `file` through a memory map of it instead of
+    /// reading it. The file must not be changed while it is parsed. Failing to map the file is
+    /// reported as a read error on line 1.
+    #[cfg(feature = "mmap")]
+    pub fn from_file_mmap(file: &std::fs::File, options: &ParseOptions) -> Result<Self, ParseError> {
+        // SAFETY: the map is only read while parsing and dropped before returning, and the caller
+        // keeps the file unchanged meanwhile, as documented.
+        let map = unsafe { memmap2::Mmap::map(file) }
+            .map_err(|e| ParseError::new(1, "", crate::ParseErrorKind::Io(e)))?;
+        Self::from_bytes_with_options(&map, options)
+    }
+
+    /// Parses an almanac from its JSON representation: a `seeds` array and an ordered `maps`
+    /// array of `{from, to, rules: [[destination, source, size], ..]}` objects.
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self, ParseError> {
+        let (seeds, chain) = parse_json(reader)?;
+        Ok(Self { seeds, chain })
+    }
+
+    /// Interprets the seeds line as `(start, size)` pairs, as in part 2 of the puzzle. Fails if the
+    /// seeds line has an odd number of values.
+    pub fn seed_ranges(&self) -> Result<Vec<(u64, u64)>, OddSeedCount> {
+        if !self.seeds.len().is_multiple_of(2) {
+            return Err(OddSeedCount { found: self.seeds.len() });
+        }
+        Ok(self.seeds
+            .chunks_exact(2)
+            .map(|s| (s[0], s[1]))
+            .collect())
+    }
+
+    /// Like `seed_ranges`, but sorted with overlapping and adjacent ranges merged, so that every
+    /// seed is in exactly one range. Hand-edited seeds lines may repeat seeds across ranges, which
+    /// would otherwise be resolved, and counted, once per range.
+    pub fn normalized_seed_ranges(&self) -> Result<Vec<(u64, u64)>, OddSeedCount> {
+        Ok(merge_ranges(self.seed_ranges()?))
+    }
+
+    /// Lowest location for any of the seeds taken as individual values (part 1).
+    pub fn lowest_location(&self) -> Option<u64> {
+        self.chain.min_location_for_seeds(&self.seeds)
+    }
+
+    /// The `(seed, location)` pair with the lowest location among the individual seeds.
+    pub fn lowest_seed(&self) -> Option<(u64, u64)> {
+        let location = self.chain.last_stage()?;
+        self.seeds
+            .iter()
+            .map(|&seed| (seed, self.chain.resolve_to(seed, location)))
+            .min_by_key(|&(_, location)| location)
+    }
+
+    /// Lowest location for any seed in the ranges of `seed_ranges` (part 2).
+    pub fn lowest_location_for_ranges(&self) -> Result<Option<u64>, OddSeedCount> {
+        Ok(self.chain.min_location(&self.seed_ranges()?))
+    }
+
+    /// The `(seed, location)` pair with the lowest location among the seeds of `seed_ranges`.
+    pub fn lowest_seed_in_ranges(&self) -> Result<Option<(u64, u64)>, OddSeedCount> {
+        let seed_ranges = self.normalized_seed_ranges()?;
+        let Some((location, _)) = self.chain.range_maps().last() else {
+            return Ok(None);
+        };
+        Ok(self.chain
+            .resolve_ranges_with_origin(&seed_ranges, location)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(start, _, seed)| (seed, start))
+            .min_by_key(|&(_, location)| location))
+    }
+
+    /// Same as `lowest_seed_in_ranges`, but only resolves the seeds where the chain may change how
+    /// it maps, as `RangeMapChain::min_location_boundary` does.
+    pub fn lowest_seed_in_ranges_boundary(&self) -> Result<Option<(u64, u64)>, OddSeedCount> {
+        let Some(location) = self.chain.last_stage() else {
+            return Ok(None);
+        };
+        Ok(self.chain
+            .boundary_seeds(&self.normalized_seed_ranges()?)
+            .into_iter()
+            .map(|seed| (seed, self.chain.resolve_to(seed, location)))
+            .min_by_key(|&(_, location)| location))
+    }
+
+    /// Cross-checks the range algorithm against resolving seeds one at a time, returning the lowest
+    /// location both agree on. See `verify_ranges`.
+    pub fn verify(&self, sample: Option<u64>) -> Result<Option<u64>, VerifyError> {
+        let resolved = self.chain.resolve_ranges_at(&self.normalized_seed_ranges()?, self.chain.len());
+        self.verify_ranges(&resolved, sample)?;
+        Ok(resolved.first().map(|&(start, _)| start))
+    }
+
+    /// Checks `resolved`, sorted location ranges claimed for the seed ranges, by resolving every
+    /// seed: each must land inside one of the ranges and the lowest location must be the start of
+    /// the first range. With `sample`, only that many evenly spread seeds of each range are
+    /// resolved and only the first check applies.
+    pub fn verify_ranges(&self, resolved: &[(u64, u64)], sample: Option<u64>) -> Result<(), VerifyError> {
+        let found = resolved.first().map(|&(start, _)| start);
+        let mut lowest = None;
+        for seed in sampled_seeds(&self.normalized_seed_ranges()?, sample) {
+            let location = self.chain.resolve_at(seed, self.chain.len());
+            if !resolved.iter().any(|&(start, size)| start <= location && location - start < size) {
+                return Err(VerifyError::Unreached { seed, location });
+            }
+            lowest = Some(lowest.map_or(location, |lowest: u64| lowest.min(location)));
+        }
+        if sample.is_none() && found != lowest {
+            return Err(VerifyError::Minimum { found, resolved: lowest });
+        }
+        Ok(())
+    }
+
+    /// Checks `found`, the lowest location some algorithm found for the seed ranges, by resolving
+    /// every seed one at a time: the lowest of their locations must be `found`. With `sample`, only
+    /// that many evenly spread seeds of each range are resolved and none may land below `found`.
+    pub fn verify_minimum(&self, found: Option<u64>, sample: Option<u64>) -> Result<(), VerifyError> {
+        let lowest = sampled_seeds(&self.normalized_seed_ranges()?, sample)
+            .map(|seed| self.chain.resolve_at(seed, self.chain.len()))
+            .min();
+        let agrees = match sample {
+            None => found == lowest,
+            Some(_) => lowest.is_none_or(|lowest| found.is_some_and(|found| found <= lowest)),
+        };
+        if !agrees {
+            return Err(VerifyError::Minimum { found, resolved: lowest });
+        }
+        Ok(())
+    }
+
+    /// Same as `lowest_seed_in_ranges`, but resolves every single seed of every range. Seed ranges
+    /// are processed in parallel when the `parallel` feature is enabled.
+    pub fn lowest_seed_in_ranges_brute_force(&self) -> Result<Option<(u64, u64)>, OddSeedCount> {
+        self.lowest_seed_in_ranges_brute_force_with_progress(|_| {})
+    }
+
+    /// Like `lowest_seed_in_ranges_brute_force`, calling `progress` every time another batch of
+    /// `BRUTE_FORCE_BATCH` seeds (or the rest of a range) has been resolved. Among seeds reaching
+    /// the same lowest location, the lowest seed is returned.
+    pub fn lowest_seed_in_ranges_brute_force_with_progress<F>(
+        &self,
+        progress: F
+    ) -> Result<Option<(u64, u64)>, OddSeedCount>
+    where
+        F: Fn(&Progress) + Sync,
+    {
+        let Some(location) = self.chain.last_stage() else {
+            return Ok(None);
+        };
+        let batches: Vec<(u64, u64)> = self
+            .normalized_seed_ranges()?
+            .into_iter()
+            .flat_map(|(start, size)| {
+                (0..size)
+                    .step_by(BRUTE_FORCE_BATCH as usize)
+                    .map(move |offset| (start + offset, BRUTE_FORCE_BATCH.min(size - offset)))
+            })
+            .collect();
+        let state = Mutex::new(Progress {
+            done: 0,
+            total: batches.iter().map(|&(_, size)| size).sum(),
+            best: None,
+        });
+        let resolve_batch = |&(start, size): &(u64, u64)| {
+            let lowest = (start..start.saturating_add(size))
+                .map(|seed| (seed, self.chain.resolve_to(seed, location)))
+                .min_by_key(|&(seed, location)| (location, seed));
+            let mut state = state.lock().unwrap();
+            state.done += size;
+            state.best = state.best.into_iter().chain(lowest).min_by_key(|&(seed, location)| (location, seed));
+            progress(&state);
+        };
+
+        #[cfg(feature = "parallel")]
+        batches.par_iter().for_each(resolve_batch);
+        #[cfg(not(feature = "parallel"))]
+        batches.iter().for_each(resolve_batch);
+
+        Ok(state.into_inner().unwrap().best)
+    }
+}
+
+/// How far a brute-force search has got, as reported to the callback of
+/// `Almanac::lowest_seed_in_ranges_brute_force_with_progress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// Seeds resolved so far.
+    pub done: u64,
+    /// Seeds in all the seed ranges.
+    pub total: u64,
+    /// The `(seed, location)` pair with the lowest location found so far.
+    pub best: Option<(u64, u64)>,
+}
+
+/// Number of seeds a brute-force search resolves between two progress reports.
+pub const BRUTE_FORCE_BATCH: u64 = 1 << 20;
+
+/// The seeds verification resolves: every seed of `seed_ranges`, or with `sample` only that many
+/// evenly spread seeds of each range, starting with its first.
+fn sampled_seeds(seed_ranges: &[(u64, u64)], sample: Option<u64>) -> impl Iterator<Item = u64> + '_ {
+    seed_ranges.iter().flat_map(move |&(start, size)| {
+        let count = sample.unwrap_or(size).min(size);
+        (0..count).map(move |i| start + i * (size / count.max(1)))
+    })
+}
+
+/// Writes the almanac in the puzzle input format, so that `Almanac::parse` reads it back.
+impl fmt::Display for Almanac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seeds: Vec<String> = self.seeds.iter().map(|seed| seed.to_string()).collect();
+        write!(f, "seeds: {}\n\n{}", seeds.join(" "), self.chain)
+    }
+}
+
+/// Parses the full text of an almanac. Shorthand for `Almanac::parse`.
+pub fn parse_almanac(text: &str) -> Result<Almanac, ParseError> {
+    Almanac::parse(text)
+}
+
+/// Solves part 1 or 2 of the puzzle for the full text of an almanac, returning the lowest location.
+/// Does no I/O of its own, so that it can run where there is no filesystem, such as a web page.
+pub fn solve_from_str(input: &str, part: u8) -> Result<u64, String> {
+    let almanac = Almanac::parse(input).map_err(|e| e.to_string())?;
+    let lowest = match part {
+        1 => almanac.lowest_location(),
+        2 => almanac.lowest_location_for_ranges().map_err(|e| e.to_string())?,
+        _ => return Err(format!("invalid part `{}`, expected 1 or 2", part)),
+    };
+    lowest.ok_or_else(|| String::from("no seeds to map"))
+}
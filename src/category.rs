@@ -0,0 +1,98 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{RangeMapChain, StageError, UnknownLabel};
+
+/// The categories of the puzzle almanac, in chain order. Chains built from other inputs may use
+/// any labels, and are resolved with the string-labeled methods of `RangeMapChain` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Category {
+    Seed,
+    Soil,
+    Fertilizer,
+    Water,
+    Light,
+    Temperature,
+    Humidity,
+    Location,
+}
+
+impl Category {
+    /// Every category, from `Seed` to `Location`.
+    pub const ALL: [Category; 8] = [
+        Category::Seed,
+        Category::Soil,
+        Category::Fertilizer,
+        Category::Water,
+        Category::Light,
+        Category::Temperature,
+        Category::Humidity,
+        Category::Location,
+    ];
+
+    /// The label of the category in the almanac, as in `seed-to-soil map:`.
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::Seed => "seed",
+            Category::Soil => "soil",
+            Category::Fertilizer => "fertilizer",
+            Category::Water => "water",
+            Category::Light => "light",
+            Category::Temperature => "temperature",
+            Category::Humidity => "humidity",
+            Category::Location => "location",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
+
+impl FromStr for Category {
+    type Err = UnknownLabel;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Category::ALL.into_iter().find(|category| category.label() == s).ok_or_else(|| UnknownLabel {
+            label: String::from(s),
+            available: Category::ALL.iter().map(|category| String::from(category.label())).collect(),
+        })
+    }
+}
+
+/// Typed counterparts of the string-labeled methods, for chains of the puzzle categories.
+impl RangeMapChain {
+
+    /// Like `resolve`, up to the map labeled with `category`.
+    pub fn resolve_category(&self, value: u64, category: Category) -> Result<u64, UnknownLabel> {
+        self.resolve(value, category.label())
+    }
+
+    /// Like `resolve_between`, from the `from` category up to the `to` one.
+    pub fn resolve_between_categories(&self, value: u64, from: Category, to: Category) -> Result<u64, StageError> {
+        self.resolve_between(value, from.label(), to.label())
+    }
+
+    /// Like `resolve_trace`, failing on the first map whose label is not one of the categories.
+    pub fn resolve_trace_categories(&self, value: u64) -> Result<Vec<(Category, u64)>, UnknownLabel> {
+        self.resolve_trace(value)
+            .into_iter()
+            .map(|(label, mapped)| Ok((label.parse()?, mapped)))
+            .collect()
+    }
+}
+
+#[test]
+fn test_category_labels() {
+    for category in Category::ALL {
+        assert_eq!(Ok(category), category.to_string().parse());
+    }
+    assert_eq!("temperature", Category::Temperature.to_string());
+    assert!(Category::Seed < Category::Location);
+
+    let error = "temprature".parse::<Category>().unwrap_err();
+    assert_eq!("temprature", error.label);
+    assert_eq!(8, error.available.len());
+}
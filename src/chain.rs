@@ -0,0 +1,747 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::{ChainError, MapValue, RangeMap, StageError, UnknownLabel};
+
+/// Identifies a map of a `RangeMapChain` by its position in the chain. Returned by
+/// `RangeMapChain::stage_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StageId(usize);
+
+/// An ordered sequence of labeled maps. Each label names the category a map
+/// resolves *to*, so resolving up to `"location"` runs every map in the chain. The category each
+/// map resolves *from* is kept alongside.
+#[derive(Debug, Default, Clone)]
+pub struct RangeMapChain {
+    range_maps: Vec<(String, RangeMap)>,
+    sources: Vec<String>,
+    /// Every map composed into one, built on the first lookup through the whole chain and dropped
+    /// whenever a map changes. `None` when a map has overlapping rules, which the composed map
+    /// could resolve differently.
+    composed: OnceLock<Option<RangeMap>>,
+}
+
+/// Chains are equal when their maps are, whether or not they have built their composed map.
+impl PartialEq for RangeMapChain {
+    fn eq(&self, other: &Self) -> bool {
+        self.range_maps == other.range_maps && self.sources == other.sources
+    }
+}
+
+impl Eq for RangeMapChain {}
+
+impl RangeMapChain {
+
+    /// Builds a chain from `(label, map)` pairs, applied in the given order. The first map is
+    /// taken to resolve from `seed` and every other map from the label of the map before it.
+    /// Fails on empty or duplicate labels.
+    pub fn new(range_maps: Vec<(String, RangeMap)>) -> Result<Self, ChainError> {
+        let sources: Vec<String> = std::iter::once(String::from("seed"))
+            .chain(range_maps.iter().map(|(label, _)| label.clone()))
+            .collect();
+        Self::from_stages(
+            sources
+                .into_iter()
+                .zip(range_maps)
+                .map(|(source, (label, range_map))| (source, label, range_map))
+                .collect()
+        )
+    }
+
+    /// Builds a chain from `(source, label, map)` triples, as read from `<source>-to-<label> map:`
+    /// sections, applied in the given order. Fails on empty or duplicate labels, and when a stage
+    /// does not resolve from the category the stage before it resolves to.
+    pub fn from_stages(stages: Vec<(String, String, RangeMap)>) -> Result<Self, ChainError> {
+        for (index, (source, label, _)) in stages.iter().enumerate() {
+            if label.is_empty() || source.is_empty() {
+                return Err(ChainError::EmptyLabel { index });
+            }
+            if stages[..index].iter().any(|(_, other, _)| other == label) {
+                return Err(ChainError::DuplicateLabel { index, label: label.clone() });
+            }
+            if let Some((_, previous, _)) = index.checked_sub(1).map(|previous| &stages[previous]) {
+                if previous != source {
+                    return Err(ChainError::Disconnected { index, expected: previous.clone(), found: source.clone() });
+                }
+            }
+        }
+        let (sources, range_maps) = stages
+            .into_iter()
+            .map(|(source, label, range_map)| (source, (label, range_map)))
+            .unzip();
+        Ok(Self { range_maps, sources, composed: OnceLock::new() })
+    }
+
+    /// Returns the category the map at `index` resolves from.
+    pub fn source(&self, index: usize) -> Option<&str> {
+        self.sources.get(index).map(String::as_str)
+    }
+
+    /// Returns the labeled maps of this chain in resolution order.
+    pub fn range_maps(&self) -> &[(String, RangeMap)] {
+        &self.range_maps
+    }
+
+    /// Number of maps in the chain.
+    pub fn len(&self) -> usize {
+        self.range_maps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range_maps.is_empty()
+    }
+
+    /// Returns the first `stages` maps of the chain, or all of them if there are fewer.
+    fn first_stages(&self, stages: usize) -> &[(String, RangeMap)] {
+        &self.range_maps[..stages.min(self.range_maps.len())]
+    }
+
+    /// Resolves `value` through the first `stages` maps of the chain, whatever their labels.
+    /// A `stages` past the end of the chain runs every map. Always walks the maps one by one, never
+    /// through the composed map, so it checks the range algorithm independently.
+    pub fn resolve_at(&self, value: u64, stages: usize) -> u64 {
+        self.first_stages(stages)
+            .iter()
+            .fold(value, |mapped, (_, range_map)| range_map.get_or_identity(mapped))
+    }
+
+    /// Like `resolve_ranges`, but stops after the first `stages` maps instead of at a label.
+    pub fn resolve_ranges_at(&self, ranges: &[(u64, u64)], stages: usize) -> Vec<(u64, u64)> {
+        map_ranges(self.first_stages(stages), ranges)
+    }
+
+    /// Returns the id of the map labeled `label`, to resolve through the chain up to that map
+    /// without looking the label up again.
+    pub fn stage_id(&self, label: &str) -> Option<StageId> {
+        self.range_maps
+            .iter()
+            .position(|(range_map_label, _)| range_map_label == label)
+            .map(StageId)
+    }
+
+    /// Returns the id of the last map of the chain, `None` if the chain is empty.
+    pub fn last_stage(&self) -> Option<StageId> {
+        self.range_maps.len().checked_sub(1).map(StageId)
+    }
+
+    /// Drops every map after the one identified by `stage`, so that resolving through the whole
+    /// chain stops at its category. Does nothing if `stage` is past the end of the chain.
+    pub fn truncate(&mut self, stage: StageId) {
+        self.range_maps.truncate(stage.0 + 1);
+        self.sources.truncate(stage.0 + 1);
+        self.composed = OnceLock::new();
+    }
+
+    /// Returns the map identified by `stage` for changing its rules, or `None` for an id past the
+    /// end of the chain.
+    pub fn range_map_mut(&mut self, stage: StageId) -> Option<&mut RangeMap> {
+        self.composed = OnceLock::new();
+        self.range_maps.get_mut(stage.0).map(|(_, range_map)| range_map)
+    }
+
+    /// The composed map of the whole chain, built on first use. `None` if a map has overlapping
+    /// rules.
+    fn composed(&self) -> Option<&RangeMap> {
+        self.composed
+            .get_or_init(|| {
+                let overlapping = self.range_maps.iter().any(|(_, range_map)| range_map.validate().is_err());
+                (!overlapping).then(|| self.compose())
+            })
+            .as_ref()
+    }
+
+    /// Whether resolving through the first `stages` maps runs the whole chain, and can use the
+    /// composed map.
+    fn is_whole(&self, stages: usize) -> bool {
+        stages >= self.range_maps.len()
+    }
+
+    /// Returns the maps of the chain up to and including the one labeled `label`.
+    fn stages(&self, label: &str) -> Result<&[(String, RangeMap)], UnknownLabel> {
+        match self.stage_id(label) {
+            Some(stage) => Ok(self.first_stages(stage.0 + 1)),
+            None => Err(UnknownLabel {
+                label: String::from(label),
+                available: self.range_maps.iter().map(|(label, _)| label.clone()).collect(),
+            }),
+        }
+    }
+
+    /// Resolves `value` through the chain up to the map labeled `label`. Values not covered by
+    /// a map pass through it unchanged, so this only fails if no map carries the given label.
+    /// Resolving up to the last map uses the composed map of the chain, built on first use.
+    pub fn resolve(&self, value: u64, label: &str) -> Result<u64, UnknownLabel> {
+        let stages = self.stages(label)?;
+        if let Some(composed) = self.composed().filter(|_| self.is_whole(stages.len())) {
+            return Ok(composed.get_or_identity(value));
+        }
+        Ok(stages.iter().fold(value, |mapped, (_, range_map)| range_map.get_or_identity(mapped)))
+    }
+
+    /// Like `resolve` for every one of `values`, in the same order. The whole batch goes through
+    /// each map at once with `RangeMap::get_many`.
+    pub fn resolve_many(&self, values: &[u64], label: &str) -> Result<Vec<u64>, UnknownLabel> {
+        let stages = self.stages(label)?;
+        if let Some(composed) = self.composed().filter(|_| self.is_whole(stages.len())) {
+            let mapped = composed.get_many(values);
+            return Ok(mapped.into_iter().zip(values).map(|(mapped, &value)| mapped.unwrap_or(value)).collect());
+        }
+        Ok(map_values(stages, values))
+    }
+
+    /// Like `resolve`, up to the map identified by `stage`. An id from another chain past the
+    /// end of this one runs every map.
+    pub fn resolve_to(&self, value: u64, stage: StageId) -> u64 {
+        match self.composed().filter(|_| self.is_whole(stage.0 + 1)) {
+            Some(composed) => composed.get_or_identity(value),
+            None => self.resolve_at(value, stage.0 + 1),
+        }
+    }
+
+    /// Like `resolve_ranges`, up to the map identified by `stage`.
+    pub fn resolve_ranges_to(&self, ranges: &[(u64, u64)], stage: StageId) -> Vec<(u64, u64)> {
+        self.resolve_ranges_at(ranges, stage.0 + 1)
+    }
+
+    /// Resolves `value`, taken as a value of the `from_label` category, through the maps that
+    /// follow it up to and including the one labeled `to_label`. Resolving from a category to
+    /// itself returns `value` unchanged.
+    pub fn resolve_between(&self, value: u64, from_label: &str, to_label: &str) -> Result<u64, StageError> {
+        Ok(self.stages_between(from_label, to_label)?
+            .iter()
+            .fold(value, |mapped, (_, range_map)| range_map.get_or_identity(mapped)))
+    }
+
+    /// Like `resolve_between`, for `(start, size)` ranges.
+    pub fn resolve_ranges_between(
+        &self,
+        ranges: &[(u64, u64)],
+        from_label: &str,
+        to_label: &str
+    ) -> Result<Vec<(u64, u64)>, StageError> {
+        Ok(map_ranges(self.stages_between(from_label, to_label)?, ranges))
+    }
+
+    /// Returns the maps resolving from the `from_label` category up to the `to_label` one.
+    fn stages_between(&self, from_label: &str, to_label: &str) -> Result<&[(String, RangeMap)], StageError> {
+        // The first category is no map's label, so it would not be found as `to_label`.
+        if from_label == to_label && self.sources.iter().any(|source| source == from_label) {
+            return Ok(&[]);
+        }
+        let end = self.stages(to_label)?.len();
+        let start = match self.sources.iter().position(|source| source == from_label) {
+            Some(start) => start,
+            None if from_label == to_label => end,
+            None => {
+                let mut available = self.sources.clone();
+                available.extend(self.range_maps.last().map(|(label, _)| label.clone()));
+                return Err(StageError::Unknown(UnknownLabel { label: String::from(from_label), available }));
+            }
+        };
+        if start > end {
+            return Err(StageError::OutOfOrder { from: String::from(from_label), to: String::from(to_label) });
+        }
+        Ok(&self.range_maps[start..end])
+    }
+
+    /// Returns the label and mapped value after each stage of the chain, in chain order.
+    pub fn resolve_trace(&self, value: u64) -> Vec<(String, u64)> {
+        let mut mapped = value;
+        self.range_maps
+            .iter()
+            .map(|(label, range_map)| {
+                mapped = range_map.get_or_identity(mapped);
+                (label.clone(), mapped)
+            })
+            .collect()
+    }
+
+    /// Walks the chain backwards from the map labeled `from_label` and returns every value of the
+    /// first category that resolves to `value`.
+    pub fn resolve_reverse(&self, value: u64, from_label: &str) -> Result<Vec<u64>, UnknownLabel> {
+        let mut candidates = vec![value];
+        for (_, range_map) in self.stages(from_label)?.iter().rev() {
+            candidates = candidates.into_iter().flat_map(|c| range_map.preimages(c)).collect();
+            candidates.sort();
+            candidates.dedup();
+        }
+        Ok(candidates)
+    }
+
+    /// Returns the parts of `seed_ranges` whose values resolve through the whole chain to a
+    /// location inside the `(start, size)` `target` interval, as sorted, disjoint `(start, size)`
+    /// ranges. The target is mapped backwards through every stage, with identity fall-through.
+    pub fn preimage(&self, target: (u64, u64), seed_ranges: &[(u64, u64)]) -> Vec<(u64, u64)> {
+        let mut ranges = merge_ranges(vec![target]);
+        for (_, range_map) in self.range_maps.iter().rev() {
+            ranges = merge_ranges(range_map.preimage_ranges(&ranges));
+        }
+        intersect_ranges(&ranges, &merge_ranges(seed_ranges.into()))
+    }
+
+    /// Collapses every stage of the chain into a single map from the first category to the last.
+    pub fn compose(&self) -> RangeMap {
+        self.range_maps
+            .iter()
+            .fold(RangeMap::default(), |composed, (_, range_map)| composed.compose(range_map))
+    }
+
+    /// Like `resolve_ranges`, but every output range also carries the input value that maps to its
+    /// start, as a `(start, size, origin)` triple.
+    pub fn resolve_ranges_with_origin(
+        &self,
+        ranges: &[(u64, u64)],
+        label: &str
+    ) -> Result<Vec<(u64, u64, u64)>, UnknownLabel> {
+        let mut mapped: Vec<(u64, u64, u64)> = ranges.iter().map(|&(start, size)| (start, size, start)).collect();
+        for (_, range_map) in self.stages(label)?.iter() {
+            mapped = mapped
+                .into_iter()
+                .flat_map(|(start, size, origin)| {
+                    let mut offset = 0;
+                    range_map.partition(start, size).into_iter().map(move |(output, output_size)| {
+                        offset += output_size;
+                        (output, output_size, origin + offset - output_size)
+                    })
+                })
+                .collect();
+        }
+        Ok(mapped)
+    }
+
+    /// Maps every `(start, size)` range through the chain up to the map labeled `label`. The ranges
+    /// are merged with `merge_ranges` after each stage, so the output is sorted and disjoint.
+    pub fn resolve_ranges(&self, ranges: &[(u64, u64)], label: &str) -> Result<Vec<(u64, u64)>, UnknownLabel> {
+        Ok(map_ranges(self.stages(label)?, ranges))
+    }
+
+    /// Like `resolve_ranges`, using the buffers of `resolver` instead of allocating new ones, for
+    /// callers resolving many ranges in a loop. The output borrows from `resolver`.
+    pub fn resolve_ranges_with<'r>(
+        &self,
+        ranges: &[(u64, u64)],
+        label: &str,
+        resolver: &'r mut Resolver
+    ) -> Result<&'r [(u64, u64)], UnknownLabel> {
+        Ok(resolver.map_ranges(self.stages(label)?, ranges))
+    }
+
+    /// Lowest location reachable from any of the `(start, size)` seed ranges, running every map of
+    /// the chain. Returns `None` if there are no seeds. Ranges that cannot beat the best location
+    /// found so far are dropped before each map: see `min_location_visiting`.
+    pub fn min_location(&self, seed_ranges: &[(u64, u64)]) -> Option<u64> {
+        self.min_location_visiting(seed_ranges).0
+    }
+
+    /// Like `min_location`, also returning how many ranges were mapped through a map. A map sends
+    /// a value to itself or to at least its lowest destination, so values of a range starting at
+    /// `start` end at a location of at least `start` or the lowest destination of the maps left,
+    /// whichever is lower. Starting from the location of the first seed, every range whose bound
+    /// is not below the best location is skipped, and the ranges of the last map stop being
+    /// mapped as soon as the next one is bounded out, as they are sorted by start.
+    pub(crate) fn min_location_visiting(&self, seed_ranges: &[(u64, u64)]) -> (Option<u64>, usize) {
+        let mut ranges = merge_ranges(seed_ranges.into());
+        let Some(&(first_seed, _)) = ranges.first() else {
+            return (None, 0);
+        };
+        // `floors[i]` is the lowest destination of the maps from the `i`-th one onwards.
+        let mut floors = vec![u64::MAX; self.range_maps.len() + 1];
+        for (i, (_, range_map)) in self.range_maps.iter().enumerate().rev() {
+            let lowest = range_map.iter().map(|&(destination, _, _)| destination).min();
+            floors[i] = floors[i + 1].min(lowest.unwrap_or(u64::MAX));
+        }
+        let mut best = self.resolve_at(first_seed, self.range_maps.len());
+        let mut visited = 0;
+        let Some(((_, last), stages)) = self.range_maps.split_last() else {
+            return (Some(best), visited);
+        };
+        let mut next = Vec::new();
+        for (floor, (_, range_map)) in floors.iter().zip(stages) {
+            if *floor >= best {
+                ranges.truncate(ranges.partition_point(|&(start, _)| start < best));
+            }
+            next.clear();
+            for &(start, size) in &ranges {
+                range_map.partition_into(start, size, &mut next);
+            }
+            visited += ranges.len();
+            merge_ranges_in_place(&mut next);
+            std::mem::swap(&mut ranges, &mut next);
+        }
+        let floor = floors[stages.len()];
+        for &(start, size) in &ranges {
+            if start.min(floor) >= best {
+                break;
+            }
+            visited += 1;
+            if let Some((location, _)) = last.map_ranges(start, size).next() {
+                best = best.min(location);
+            }
+        }
+        (Some(best), visited)
+    }
+
+    /// Lowest location reachable from any of the individual `seeds`, running every map of the
+    /// chain. Returns `None` if there are no seeds.
+    pub fn min_location_for_seeds(&self, seeds: &[u64]) -> Option<u64> {
+        map_values(&self.range_maps, seeds).into_iter().min()
+    }
+
+    /// Lowest location reachable from any of the `(start, size)` seed ranges, found by resolving
+    /// only the seeds where the chain may change how it maps: see `boundary_seeds`. Independent of
+    /// `min_location`, so either can check the other.
+    pub fn min_location_boundary(&self, seed_ranges: &[(u64, u64)]) -> Option<u64> {
+        self.boundary_seeds(seed_ranges)
+            .into_iter()
+            .map(|seed| self.resolve_at(seed, self.len()))
+            .min()
+    }
+
+    /// Seeds of the `(start, size)` seed ranges at which the whole chain may stop being a single
+    /// translation: the start of every seed range, and every seed that reaches the start or the
+    /// end of a rule's source interval at some stage, found by walking that boundary backwards.
+    /// Between two consecutive boundary seeds locations only increase, so the lowest location is
+    /// reached at one of them. Sorted and deduplicated.
+    pub(crate) fn boundary_seeds(&self, seed_ranges: &[(u64, u64)]) -> Vec<u64> {
+        let mut seeds: Vec<u64> = seed_ranges
+            .iter()
+            .filter(|&&(_, size)| size > 0)
+            .map(|&(start, _)| start)
+            .collect();
+        for (stage, (_, range_map)) in self.range_maps.iter().enumerate() {
+            let mut boundaries: Vec<u64> = range_map
+                .iter()
+                .flat_map(|&(_, source, size)| [source, source.saturating_add(size)])
+                .collect();
+            for (_, previous) in self.range_maps[..stage].iter().rev() {
+                boundaries = boundaries.into_iter().flat_map(|value| previous.preimages(value)).collect();
+                boundaries.sort_unstable();
+                boundaries.dedup();
+            }
+            seeds.extend(boundaries);
+        }
+        seeds.retain(|&seed| seed_ranges.iter().any(|&(start, size)| start <= seed && seed - start < size));
+        seeds.sort_unstable();
+        seeds.dedup();
+        seeds
+    }
+
+    /// The `n` lowest distinct locations reachable from the `(start, size)` seed ranges, in
+    /// ascending order. Fewer are returned if the seeds reach fewer than `n` locations.
+    pub fn lowest_locations(&self, seed_ranges: &[(u64, u64)], n: usize) -> Vec<u64> {
+        map_ranges(&self.range_maps, seed_ranges)
+            .into_iter()
+            .flat_map(|(start, size)| (0..size).map(move |offset| start + offset))
+            .take(n)
+            .collect()
+    }
+
+    /// Number of distinct locations reachable from the `(start, size)` seed ranges. The location
+    /// ranges are merged before their sizes are summed, so a location reached from several seeds
+    /// counts once.
+    pub fn count_locations(&self, seed_ranges: &[(u64, u64)]) -> u64 {
+        map_ranges(&self.range_maps, seed_ranges).iter().map(|&(_, size)| size).sum()
+    }
+
+    /// Location ranges of the `(start, size)` seed ranges, unsorted and not merged, so that every
+    /// seed is in exactly one of them: a location reached by two seeds is in two ranges. Seeds in
+    /// several seed ranges count once.
+    fn location_ranges_by_seed(&self, seed_ranges: &[(u64, u64)]) -> Vec<(u64, u64)> {
+        let mut ranges = merge_ranges(seed_ranges.into());
+        for (_, range_map) in self.range_maps.iter() {
+            ranges = ranges.into_iter().flat_map(|(start, size)| range_map.partition(start, size)).collect();
+        }
+        ranges.retain(|&(_, size)| size > 0);
+        ranges
+    }
+
+    /// The `k`-th smallest location reached by the seeds of the `(start, size)` seed ranges, from
+    /// `k = 1` for the lowest. Every seed counts once, so a location reached by two seeds takes two
+    /// places; seeds in several seed ranges count once. Found by binary search over the location
+    /// ranges, without enumerating seeds. Returns `None` if `k` is 0 or there are fewer seeds.
+    pub fn kth_location(&self, seed_ranges: &[(u64, u64)], k: u64) -> Option<u64> {
+        let index = k.checked_sub(1)?;
+        let ranges = self.location_ranges_by_seed(seed_ranges);
+        // Number of seeds reaching a location up to and including `location`.
+        let reaching = |location: u64| {
+            ranges
+                .iter()
+                .filter(|&&(start, _)| start <= location)
+                .fold(0u64, |count, &(start, size)| count.saturating_add((location - start).min(size - 1) + 1))
+        };
+        let mut low = ranges.iter().map(|&(start, _)| start).min()?;
+        let mut high = ranges.iter().map(|&(start, size)| start + (size - 1)).max()?;
+        if reaching(high) <= index {
+            return None;
+        }
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if reaching(middle) > index {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        Some(low)
+    }
+
+    /// Counts the seeds of the `(start, size)` seed ranges by location, in buckets of
+    /// `bucket_size` consecutive locations. Returns `(bucket start, seeds)` pairs sorted by bucket,
+    /// leaving out empty buckets. Counted from the sizes of the location ranges, without
+    /// enumerating seeds. Panics if `bucket_size` is 0.
+    pub fn location_histogram(&self, seed_ranges: &[(u64, u64)], bucket_size: u64) -> Vec<(u64, u64)> {
+        assert!(bucket_size > 0, "buckets must hold at least one location");
+        let mut buckets: BTreeMap<u64, u64> = BTreeMap::new();
+        for (start, size) in self.location_ranges_by_seed(seed_ranges) {
+            let last = start + (size - 1);
+            for bucket in start / bucket_size..=last / bucket_size {
+                let bucket_start = bucket * bucket_size;
+                let bucket_last = bucket_start.saturating_add(bucket_size - 1);
+                let count = bucket_last.min(last) - bucket_start.max(start) + 1;
+                *buckets.entry(bucket_start).or_default() += count;
+            }
+        }
+        buckets.into_iter().collect()
+    }
+
+    /// Like `kth_location`, but among distinct locations: a location reached by several seeds takes
+    /// a single place.
+    pub fn kth_distinct_location(&self, seed_ranges: &[(u64, u64)], k: u64) -> Option<u64> {
+        let mut index = k.checked_sub(1)?;
+        for (start, size) in map_ranges(&self.range_maps, seed_ranges) {
+            if index < size {
+                return Some(start + index);
+            }
+            index -= size;
+        }
+        None
+    }
+
+    /// The `n` lowest distinct locations reachable from the individual `seeds`, in ascending order.
+    pub fn lowest_locations_for_seeds(&self, seeds: &[u64], n: usize) -> Vec<u64> {
+        let mut locations = map_values(&self.range_maps, seeds);
+        locations.sort_unstable();
+        locations.dedup();
+        locations.truncate(n);
+        locations
+    }
+}
+
+/// Maps every one of `values` through every one of `stages`, keeping their order.
+fn map_values(stages: &[(String, RangeMap)], values: &[u64]) -> Vec<u64> {
+    let mut mapped = values.to_vec();
+    for (_, range_map) in stages.iter() {
+        let looked_up = range_map.get_many(&mapped);
+        for (value, looked_up) in mapped.iter_mut().zip(looked_up) {
+            *value = looked_up.unwrap_or(*value);
+        }
+    }
+    mapped
+}
+
+/// Maps `(start, size)` ranges through every one of `stages`, merging them after each stage.
+fn map_ranges(stages: &[(String, RangeMap)], ranges: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut resolver = Resolver::new();
+    resolver.map_ranges(stages, ranges);
+    resolver.mapped
+}
+
+/// Scratch buffers for resolving `(start, size)` ranges through a chain. Each stage reads the
+/// ranges of one buffer and writes into the other, so once both have grown large enough a
+/// `Resolver` kept across calls to `RangeMapChain::resolve_ranges_with` stops allocating.
+#[derive(Debug, Default, Clone)]
+pub struct Resolver {
+    mapped: Vec<(u64, u64)>,
+    next: Vec<(u64, u64)>,
+}
+
+impl Resolver {
+    /// Starts with empty buffers, which grow on the first calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps `ranges` through every map of `stages`, merging after each stage like `merge_ranges`,
+    /// and returns the result, which stays borrowed from the buffers until the next call.
+    fn map_ranges(&mut self, stages: &[(String, RangeMap)], ranges: &[(u64, u64)]) -> &[(u64, u64)] {
+        self.mapped.clear();
+        self.mapped.extend_from_slice(ranges);
+        merge_ranges_in_place(&mut self.mapped);
+        for (_, range_map) in stages.iter() {
+            self.next.clear();
+            for &(start, size) in &self.mapped {
+                range_map.partition_into(start, size, &mut self.next);
+            }
+            merge_ranges_in_place(&mut self.next);
+            std::mem::swap(&mut self.mapped, &mut self.next);
+        }
+        &self.mapped
+    }
+}
+
+/// Builds a `RangeMapChain` one stage at a time, starting from a given category:
+/// `ChainBuilder::new("seed").stage("soil", seed_to_soil).stage("fertilizer", soil_to_fertilizer)`.
+/// Stages are checked when the chain is built.
+#[derive(Debug, Clone)]
+pub struct ChainBuilder {
+    start: String,
+    stages: Vec<(String, String, RangeMap)>,
+}
+
+impl ChainBuilder {
+
+    /// Starts a chain whose first stage resolves from the `start` category.
+    pub fn new(start: &str) -> Self {
+        Self { start: String::from(start), stages: Vec::new() }
+    }
+
+    /// Adds a stage resolving to `label` from the category the previous stage resolves to, or
+    /// from the start category for the first stage.
+    pub fn stage(self, label: &str, range_map: RangeMap) -> Self {
+        let source = self.stages.last().map_or(&self.start, |(_, previous, _)| previous).clone();
+        self.stage_from(&source, label, range_map)
+    }
+
+    /// Adds a stage resolving from `source` to `label`. Building fails unless `source` is the
+    /// category the previous stage resolves to, or the start category for the first stage.
+    pub fn stage_from(mut self, source: &str, label: &str, range_map: RangeMap) -> Self {
+        self.stages.push((String::from(source), String::from(label), range_map));
+        self
+    }
+
+    /// Builds the chain, failing like `RangeMapChain::from_stages` on empty or duplicate labels
+    /// and on stages that do not connect, including a first stage not resolving from the start
+    /// category.
+    pub fn build(self) -> Result<RangeMapChain, ChainError> {
+        if let Some((source, _, _)) = self.stages.first() {
+            if *source != self.start {
+                return Err(ChainError::Disconnected { index: 0, expected: self.start, found: source.clone() });
+            }
+        }
+        RangeMapChain::from_stages(self.stages)
+    }
+}
+
+/// Writes every map as a `<from>-to-<to> map:` section, separated by blank lines.
+impl fmt::Display for RangeMapChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, ((label, range_map), source)) in self.range_maps.iter().zip(self.sources.iter()).enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}-to-{} map:\n{}", source, label, range_map)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes `chain` as a Graphviz DOT digraph with one node per category, in chain order, and one
+/// edge per map labeled with its number of rules and the number of source values they cover.
+pub fn to_dot(chain: &RangeMapChain) -> String {
+    let quote = |label: &str| format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut dot = String::from("digraph almanac {\n");
+    for category in chain.sources.first().into_iter().chain(chain.range_maps.iter().map(|(label, _)| label)) {
+        dot.push_str(&format!("    {};\n", quote(category)));
+    }
+    for ((label, range_map), source) in chain.range_maps.iter().zip(&chain.sources) {
+        let rules = if range_map.len() == 1 { "rule" } else { "rules" };
+        dot.push_str(&format!(
+            "    {} -> {} [label=\"{} {}, {} covered\"];\n",
+            quote(source), quote(label), range_map.len(), rules, range_map.covered_size()
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Intersects two sorted, disjoint lists of `(start, size)` ranges.
+pub(crate) fn intersect_ranges<T: MapValue>(first: &[(T, T)], second: &[(T, T)]) -> Vec<(T, T)> {
+    let mut intersection = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < first.len() && j < second.len() {
+        let (first_start, first_end) = (first[i].0, first[i].0 + first[i].1);
+        let (second_start, second_end) = (second[j].0, second[j].0 + second[j].1);
+        let (start, end) = (first_start.max(second_start), first_end.min(second_end));
+        if start < end {
+            intersection.push((start, end - start));
+        }
+        if first_end < second_end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    intersection
+}
+
+/// Sorts `(start, size)` ranges by start and merges the ones that overlap or touch. Empty ranges
+/// are dropped.
+pub fn merge_ranges<T: MapValue>(mut ranges: Vec<(T, T)>) -> Vec<(T, T)> {
+    merge_ranges_in_place(&mut ranges);
+    ranges
+}
+
+/// Like `merge_ranges`, reusing the storage of `ranges` for the merged ones.
+fn merge_ranges_in_place<T: MapValue>(ranges: &mut Vec<(T, T)>) {
+    ranges.retain(|&(_, size)| size > T::ZERO);
+    ranges.sort_unstable();
+    let mut merged: usize = 0;
+    for i in 0..ranges.len() {
+        let (start, size) = ranges[i];
+        match merged.checked_sub(1).map(|last| &mut ranges[last]) {
+            Some((last_start, last_size)) if start <= last_start.saturating_add(*last_size) => {
+                *last_size = (*last_size).max(start.saturating_add(size) - *last_start);
+            }
+            _ => {
+                ranges[merged] = (start, size);
+                merged += 1;
+            }
+        }
+    }
+    ranges.truncate(merged);
+}
+
+/// Whether two lists of `(start, size)` ranges cover exactly the same values, regardless of their
+/// order and of how the values are split into ranges.
+pub fn same_ranges<T: MapValue>(first: &[(T, T)], second: &[(T, T)]) -> bool {
+    merge_ranges(first.into()) == merge_ranges(second.into())
+}
+
+#[test]
+fn test_merge_ranges() {
+    assert_eq!(
+        vec![(0, 15), (20, 5), (30, 10)],
+        merge_ranges(vec![(30u64, 10), (5, 10), (0, 5), (20, 5), (32, 2), (7, 1), (50, 0)])
+    );
+    // Ranges running past `u64::MAX` are merged as if they ended there.
+    let max = u64::MAX;
+    assert_eq!(vec![(max - 10, 20)], merge_ranges(vec![(max - 5, 2), (max - 10, 20), (max - 2, 5)]));
+}
+
+#[test]
+fn test_same_ranges() {
+    assert!(same_ranges::<u64>(&[(10, 5), (0, 5)], &[(0, 5), (10, 5)]));
+    assert!(same_ranges::<u64>(&[(0, 10), (20, 0)], &[(5, 5), (0, 5)]));
+    assert!(!same_ranges::<u64>(&[(0, 10)], &[(0, 9)]));
+    assert!(same_ranges::<u64>(&[], &[(3, 0)]));
+}
+
+#[test]
+fn test_min_location_stops_early() {
+    let chain = ChainBuilder::new("seed")
+        .stage("soil", RangeMap::new(vec![(0, 500, 10)]))
+        .stage("location", RangeMap::new(vec![(2000, 2000, 1)]))
+        .build()
+        .unwrap();
+    let seed_ranges = [(100, 5), (500, 5), (600, 5)];
+    let expected = chain.resolve_ranges(&seed_ranges, "location").unwrap()[0].0;
+
+    // Seed 100 reaches location 100, so only the soil range 0..5 that 500..505 maps to can beat
+    // it: the soil ranges 100..105 and 600..605 are never mapped to locations.
+    assert_eq!((Some(expected), 3 + 1), chain.min_location_visiting(&seed_ranges));
+
+    // Nothing can beat location 0, reached from seed 0: no range is mapped at all.
+    assert_eq!((Some(0), 0), chain.min_location_visiting(&[(1000, 5), (0, 1), (600, 5)]));
+    assert_eq!((None, 0), chain.min_location_visiting(&[(5, 0)]));
+}
@@ -0,0 +1,276 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+use crate::Overlap;
+
+/// What went wrong while parsing an almanac.
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// The input ended before the seeds line.
+    UnexpectedEof,
+    /// The seeds line, and the lines continuing it, hold no seeds.
+    NoSeeds,
+    /// The input starts with a section instead of a seeds line.
+    MissingSeedsLine,
+    /// The underlying reader failed.
+    Io(io::Error),
+    /// A rule line did not have exactly three numbers. `section` is the `<from>-to-<to>` header of
+    /// the section the line is in, if any.
+    WrongFieldCount { found: usize, section: Option<String> },
+    /// A token could not be parsed as a number.
+    InvalidNumber(String),
+    /// A line is neither a rule nor a `<from>-to-<to> map:` section header.
+    UnknownHeader,
+    /// A section header repeats the one on line `first_line`.
+    DuplicateSection { first_line: usize },
+    /// A section maps from `category`, as the section on line `first_line` already does.
+    BranchingSection { category: String, first_line: usize },
+    /// A section maps back to `category`, which the sections before it on the path already reach.
+    SectionCycle { category: String },
+    /// A section is not on the path of sections that starts from the `start` category.
+    UnreachableSection { start: String },
+    /// A rule appears before the first section header.
+    RuleOutsideSection,
+    /// A rule's source or destination interval runs past the largest value of the `bits`-bit type
+    /// the rule is read as.
+    RuleOverflow { bits: u32 },
+    /// A `(start, size)` pair of the seeds line, read as a part 2 seed range, runs past `u64::MAX`.
+    SeedRangeOverflow,
+    /// The sections do not form a valid chain.
+    InvalidChain(ChainError),
+    /// A section has rules with overlapping source ranges. Raised by strict parsing, and for sections
+    /// merged from repeated headers whatever the parsing mode.
+    OverlappingRules(Vec<Overlap>),
+    /// A JSON almanac is malformed or does not describe a valid chain.
+    Json(serde_json::Error),
+}
+
+/// An error raised while parsing an almanac, carrying the 1-based line number and the
+/// offending line.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub text: String,
+    pub kind: ParseErrorKind,
+    /// Byte range of the offending part of `text`, such as a token that is not a number, when the
+    /// error is about a part of the line rather than the line as a whole.
+    pub span: Option<Range<usize>>,
+}
+
+impl ParseError {
+    pub fn new(line: usize, text: &str, kind: ParseErrorKind) -> Self {
+        Self { line, text: String::from(text), kind, span: None }
+    }
+
+    /// Points the error at the `span` byte range of its line.
+    pub fn with_span(self, span: Range<usize>) -> Self {
+        Self { span: Some(span), ..self }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedEof => write!(f, "unexpected end of input, expected a seeds line"),
+            ParseErrorKind::NoSeeds => write!(f, "no seeds found"),
+            ParseErrorKind::MissingSeedsLine => write!(f, "expected a `seeds:` line before the first section"),
+            ParseErrorKind::Io(e) => write!(f, "could not read line: {}", e),
+            ParseErrorKind::WrongFieldCount { found, section: None } => {
+                write!(f, "expected 3 numbers, found {}", found)
+            }
+            ParseErrorKind::WrongFieldCount { found, section: Some(section) } => {
+                write!(f, "expected 3 numbers in a rule of section `{}`, found {}", section, found)
+            }
+            ParseErrorKind::InvalidNumber(token) => write!(f, "invalid number `{}`", token),
+            ParseErrorKind::UnknownHeader => write!(f, "expected a `<from>-to-<to> map:` section header"),
+            ParseErrorKind::DuplicateSection { first_line } => {
+                write!(f, "section already started on line {}", first_line)
+            }
+            ParseErrorKind::BranchingSection { category, first_line } => {
+                write!(f, "category `{}` is already mapped by the section on line {}", category, first_line)
+            }
+            ParseErrorKind::SectionCycle { category } => {
+                write!(f, "section maps back to category `{}`, forming a cycle", category)
+            }
+            ParseErrorKind::UnreachableSection { start } => {
+                write!(f, "section cannot be reached from category `{}`", start)
+            }
+            ParseErrorKind::RuleOutsideSection => write!(f, "rule before any `<from>-to-<to> map:` section"),
+            ParseErrorKind::RuleOverflow { bits } => {
+                write!(f, "rule overflows, source + size and destination + size must fit in {} bits", bits)
+            }
+            ParseErrorKind::SeedRangeOverflow => write!(f, "seed range overflows, start + size must fit in 64 bits"),
+            ParseErrorKind::InvalidChain(e) => write!(f, "{}", e),
+            ParseErrorKind::OverlappingRules(overlaps) => write!(
+                f,
+                "{} pair(s) of rules with overlapping source ranges, first at {}..{}",
+                overlaps.len(),
+                overlaps[0].start,
+                overlaps[0].start + overlaps[0].size
+            ),
+            ParseErrorKind::Json(e) => write!(f, "invalid JSON almanac: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::Io(e) => Some(e),
+            ParseErrorKind::InvalidChain(e) => Some(e),
+            ParseErrorKind::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The seeds line cannot be read as `(start, size)` pairs because it has an odd number of values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OddSeedCount {
+    pub found: usize,
+}
+
+impl fmt::Display for OddSeedCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "part 2 requires an even number of seed values, found {}", self.found)
+    }
+}
+
+impl Error for OddSeedCount {}
+
+/// The range algorithm and resolving seeds one at a time disagree, as found by `Almanac::verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    OddSeedCount(OddSeedCount),
+    /// The lowest location found differs from the lowest location of the seeds resolved one at a
+    /// time, or is above the location of one of them.
+    Minimum { found: Option<u64>, resolved: Option<u64> },
+    /// A seed resolves to a location that none of the location ranges contains.
+    Unreached { seed: u64, location: u64 },
+}
+
+impl From<OddSeedCount> for VerifyError {
+    fn from(e: OddSeedCount) -> Self {
+        VerifyError::OddSeedCount(e)
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = |location: &Option<u64>| location.map_or(String::from("none"), |l| l.to_string());
+        match self {
+            VerifyError::OddSeedCount(e) => write!(f, "{}", e),
+            VerifyError::Minimum { found, resolved } => write!(
+                f,
+                "the lowest location found is {} but resolving seeds one at a time gives {}",
+                location(found), location(resolved)
+            ),
+            VerifyError::Unreached { seed, location } => {
+                write!(f, "seed {} resolves to location {}, which the range algorithm does not reach", seed, location)
+            }
+        }
+    }
+}
+
+impl Error for VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VerifyError::OddSeedCount(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A chain was asked to resolve up to a label that none of its maps carries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownLabel {
+    pub label: String,
+    pub available: Vec<String>,
+}
+
+impl fmt::Display for UnknownLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown stage label '{}', available: {}", self.label, self.available.join(", "))
+    }
+}
+
+impl Error for UnknownLabel {}
+
+/// A range of stages between two categories could not be found in a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StageError {
+    Unknown(UnknownLabel),
+    /// The `from` category comes after the `to` category in the chain.
+    OutOfOrder { from: String, to: String },
+}
+
+impl From<UnknownLabel> for StageError {
+    fn from(e: UnknownLabel) -> Self {
+        StageError::Unknown(e)
+    }
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::Unknown(e) => write!(f, "{}", e),
+            StageError::OutOfOrder { from, to } => write!(f, "stage '{}' comes after '{}' in the chain", from, to),
+        }
+    }
+}
+
+impl Error for StageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StageError::Unknown(e) => Some(e),
+            StageError::OutOfOrder { .. } => None,
+        }
+    }
+}
+
+/// The stages given to a `RangeMapChain` constructor do not form a valid chain. `index` is the
+/// position of the offending stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    EmptyLabel { index: usize },
+    DuplicateLabel { index: usize, label: String },
+    /// The stage resolves from `found`, but the stage before it resolves to `expected`.
+    Disconnected { index: usize, expected: String, found: String },
+}
+
+impl ChainError {
+    /// Position of the offending stage in the chain.
+    pub fn index(&self) -> usize {
+        match self {
+            ChainError::EmptyLabel { index }
+            | ChainError::DuplicateLabel { index, .. }
+            | ChainError::Disconnected { index, .. } => *index,
+        }
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::EmptyLabel { index } => write!(f, "stage {} has an empty label", index + 1),
+            ChainError::DuplicateLabel { index, label } => {
+                write!(f, "stage {} resolves to '{}', which an earlier stage already does", index + 1, label)
+            }
+            ChainError::Disconnected { index, expected, found } => write!(
+                f,
+                "stage {} resolves from '{}', but the stage before it resolves to '{}'",
+                index + 1, found, expected
+            ),
+        }
+    }
+}
+
+impl Error for ChainError {}
@@ -0,0 +1,101 @@
+//! Reproducible synthetic almanacs, shared by the benchmarks and tests.
+
+use crate::{Almanac, RangeMap, RangeMapChain};
+
+/// Values are drawn from `0..SPACE`, the same magnitude as real puzzle inputs.
+const SPACE: u64 = 1 << 32;
+
+/// Shape of a generated almanac.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratorConfig {
+    /// Number of maps in the chain. The last one is labeled `location`.
+    pub stages: usize,
+    pub rules_per_stage: usize,
+    /// Number of `(start, size)` pairs on the seeds line.
+    pub seed_ranges: usize,
+    pub seed_range_size: u64,
+    /// Seed of the random number generator; equal configs generate equal almanacs.
+    pub rng_seed: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            stages: 7,
+            rules_per_stage: 40,
+            seed_ranges: 10,
+            seed_range_size: 100_000_000,
+            rng_seed: 5,
+        }
+    }
+}
+
+/// splitmix64, which is plenty for generating test data without pulling in a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value in `0..bound`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound.max(1)
+    }
+}
+
+/// Generates a map whose rules do not overlap, with random gaps between them.
+fn generate_map(rng: &mut Rng, rules: usize) -> RangeMap {
+    let average = SPACE / (rules as u64 + 1);
+    let mut source = 0;
+    let mut ranges = Vec::with_capacity(rules);
+    for _ in 0..rules {
+        source += rng.below(average / 4);
+        let size = 1 + rng.below(average);
+        let destination = rng.below(SPACE - size);
+        ranges.push((destination, source, size));
+        source += size;
+    }
+    RangeMap::new(ranges)
+}
+
+/// Generates an almanac with the given shape.
+pub fn generate(config: &GeneratorConfig) -> Almanac {
+    let mut rng = Rng(config.rng_seed);
+    let seeds = (0..config.seed_ranges)
+        .flat_map(|_| {
+            let size = config.seed_range_size;
+            [rng.below(SPACE - size), size]
+        })
+        .collect();
+    let chain = RangeMapChain::new(
+        (1..=config.stages)
+            .map(|stage| {
+                let label = if stage == config.stages { String::from("location") } else { format!("stage{}", stage) };
+                (label, generate_map(&mut rng, config.rules_per_stage))
+            })
+            .collect()
+    )
+    .expect("generated labels are unique");
+    Almanac { seeds, chain }
+}
+
+#[test]
+fn test_generate() {
+    let config = GeneratorConfig { stages: 3, rules_per_stage: 25, seed_ranges: 4, ..GeneratorConfig::default() };
+    let almanac = generate(&config);
+
+    assert_eq!(almanac, generate(&config));
+    assert_ne!(almanac, generate(&GeneratorConfig { rng_seed: 6, ..config.clone() }));
+    assert_eq!(8, almanac.seeds.len());
+    assert_eq!(3, almanac.chain.range_maps().len());
+    for (_, range_map) in almanac.chain.range_maps() {
+        assert_eq!(25, range_map.ranges().len());
+        assert_eq!(Ok(()), range_map.validate());
+    }
+    assert!(almanac.lowest_location_for_ranges().unwrap().is_some());
+}
@@ -0,0 +1,22 @@
+//! Range mapping logic for the Advent of Code 2023 day 5 almanac.
+
+mod almanac;
+mod category;
+mod chain;
+mod error;
+pub mod generate;
+mod parse;
+#[cfg(feature = "python")]
+mod python;
+mod range_map;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use almanac::{parse_almanac, solve_from_str, Almanac, Progress, BRUTE_FORCE_BATCH};
+pub use category::Category;
+pub use chain::{merge_ranges, same_ranges, to_dot, ChainBuilder, RangeMapChain, Resolver, StageId};
+pub use error::{ChainError, OddSeedCount, ParseError, ParseErrorKind, StageError, UnknownLabel, VerifyError};
+pub use parse::ParseOptions;
+pub use range_map::{MapValue, Overlap, Precedence, RangeMap};
@@ -1,215 +1,1504 @@
 use std::env;
 use std::fs;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Default)]
-struct RangeMap {
-    ranges: Vec<(usize, usize, usize)>
+use aoc2023_day05::{
+    merge_ranges, to_dot, Almanac, ParseError, ParseOptions, Progress, RangeMap, RangeMapChain, Resolver,
+};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+
+const USAGE: &str = "usage: aoc2023-day05 [solve] [--part 1|2] [--seed N]... [--seed-range START:LEN]... [--range START:LEN|START..END]... [--trace[=SEED]] [--explain SEED[,SEED]...] [--reverse LOCATION] [--queries FILE] [--repl] [--top N] [--kth K [--distinct]] [--count-locations] [--all] [--histogram WIDTH] [--stage LABEL] [--emit-dot FILE] [--emit-composed FILE [--include-identity]] [--output text|json|csv] [--algorithm ranges|boundary|brute-force] [--brute-force] [--verify [--sample N]] [--validate] [--quiet] [--strict] [--merge-duplicate-sections] [--gzip] [--input-format text|json] [--help] [PATH|-]...
+       aoc2023-day05 trace|reverse|compose|validate|stats --help";
+
+/// Printed by `--help`, with one line per flag. New flags must be listed here and in `USAGE`.
+const HELP: &str = "Finds the lowest location number reached by the seeds of an almanac (Advent of Code 2023, day 5).
+
+Reads every PATH in turn, or stdin when PATH is `-`. Without any PATH, reads the file named by the
+AOC_DAY05_INPUT environment variable, or else stdin unless it is a terminal.
+
+subcommands, each with its own --help:
+  solve                      find the lowest location, the default when no subcommand is given
+  trace                      print the value of seeds at each stage
+  reverse                    print the seeds that reach a location
+  compose                    print the almanac with its maps composed into one
+  validate                   report problems of the almanac
+  stats                      print the size of the seeds and of every map
+
+options:
+  --part 1|2                 solve part 1 (seeds are values) or part 2 (seeds are ranges, the default)
+  --seed N                   replace the seeds line with N, repeatable, part 1 only
+  --seed-range START:LEN     replace the seeds line with a range, repeatable, part 2 only
+  --range START:LEN|START..END
+                             solve for the seeds of the range only, repeatable and merged, part 2 only
+  --trace[=SEED]             print the value of SEED, or of every seed, at each stage
+  --explain SEED[,SEED]...   print the rule applied to SEED at each stage
+  --reverse LOCATION         print the seeds that reach LOCATION
+  --queries FILE             print the location of every `[CATEGORY] VALUE` line of FILE, seeds by default
+  --repl                     read commands from stdin to query the almanac, `help` lists them
+  --top N                    print the N lowest locations instead of only the minimum
+  --kth K                    print the K-th lowest location, counting every seed reaching it
+  --distinct                 with --kth, count every location once
+  --count-locations          print how many distinct locations the seeds reach
+  --all                      print the minimum location of every seed range, then the overall one
+  --histogram WIDTH          print how many seeds reach each bucket of WIDTH locations
+  --stage LABEL              stop at the LABEL category instead of location
+  --emit-dot FILE            also write the chain of categories to FILE as a Graphviz digraph
+  --emit-composed FILE       also write the almanac with its maps composed into one to FILE
+  --include-identity         with --emit-composed, write a rule for the values mapped to themselves
+  --output text|json|csv     format of the answer, text by default
+  --algorithm ranges|boundary|brute-force
+                             how part 2 finds the minimum, ranges by default
+  --brute-force              same as --algorithm brute-force
+  --verify                   check the lowest location against resolving every seed
+  --sample N                 with --verify, resolve only N seeds of each range
+  --validate                 report problems of the almanac instead of solving it
+  --quiet                    do not report brute-force progress
+  --strict                   reject maps with overlapping rules
+  --merge-duplicate-sections add the rules of a repeated section to the first one
+  --gzip                     decompress the input, implied by a `.gz` path
+  --input-format text|json   format of the input, detected when not given
+  -h, --help                 print this help";
+
+/// Command line options.
+struct Options {
+    command: Command,
+    /// Inputs to read, stdin if empty. Several inputs are solved one after the other.
+    paths: Vec<String>,
+    part: u8,
+    trace: Option<Trace>,
+    /// Seeds to show the rule applied at every stage for.
+    explain: Vec<u64>,
+    output: Output,
+    algorithm: Algorithm,
+    verify: bool,
+    /// Resolve only this many seeds of each range when verifying.
+    sample: Option<u64>,
+    /// Do not report the progress of a brute-force search.
+    quiet: bool,
+    strict: bool,
+    merge_duplicate_sections: bool,
+    /// Check the almanac and report its problems instead of solving it.
+    validate: bool,
+    /// Decompress the input, implied by a `.gz` path.
+    gzip: bool,
+    /// Format of the input, detected from its first character when not given.
+    input_format: Option<InputFormat>,
+    /// Seeds replacing the seeds line, from `--seed` (part 1) or `--seed-range` (part 2).
+    seeds: Vec<u64>,
+    seed_ranges: Vec<(u64, u64)>,
+    /// Seed ranges to solve for instead of the seeds line, from `--range`, merged once read.
+    ranges: Vec<(u64, u64)>,
+    reverse: Option<u64>,
+    /// File of values to resolve, one `[category] value` per line.
+    queries: Option<String>,
+    /// Answer commands read from stdin instead of solving.
+    repl: bool,
+    /// Print this many of the lowest locations instead of only the minimum.
+    top: Option<usize>,
+    /// Print the location at this place in ascending order instead of the minimum, from 1.
+    kth: Option<u64>,
+    /// Count locations reached by several seeds once for `kth`.
+    distinct: bool,
+    /// Print the number of distinct locations instead of the minimum.
+    count_locations: bool,
+    /// Print the minimum location of every seed range before the overall one, part 2 only.
+    all: bool,
+    /// Print how many seeds reach each bucket of this many locations instead of the minimum.
+    histogram: Option<u64>,
+    /// Category to stop resolving at instead of the last one of the chain.
+    stage: Option<String>,
+    /// File to write the chain to as a DOT digraph, besides the answer.
+    emit_dot: Option<String>,
+    /// File to write the almanac to with its maps composed into a single one, besides the answer.
+    emit_composed: Option<String>,
+    /// Keep the identity rules of the composed map, and cover its gaps with more of them.
+    include_identity: bool,
+    /// Print the help screen and exit.
+    help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            command: Command::Solve,
+            paths: Vec::new(),
+            part: 2,
+            trace: None,
+            explain: Vec::new(),
+            output: Output::Text,
+            algorithm: Algorithm::Ranges,
+            verify: false,
+            sample: None,
+            quiet: false,
+            strict: false,
+            merge_duplicate_sections: false,
+            validate: false,
+            gzip: false,
+            input_format: None,
+            seeds: Vec::new(),
+            seed_ranges: Vec::new(),
+            ranges: Vec::new(),
+            reverse: None,
+            queries: None,
+            repl: false,
+            top: None,
+            kth: None,
+            distinct: false,
+            count_locations: false,
+            all: false,
+            histogram: None,
+            stage: None,
+            emit_dot: None,
+            emit_composed: None,
+            include_identity: false,
+            help: false,
+        }
+    }
 }
 
-impl RangeMap {
-    
-    fn get(&self, value: usize) -> Option<usize> {
-        for (destination, source, size) in self.ranges.iter() {
-            if *source <= value && value < source + size {
-                return Some(destination + value - source);
+/// How the answer is printed.
+enum Output {
+    Text,
+    Json,
+    /// One row per seed with its value in every category (part 1), or one row per seed range
+    /// fragment and the location range it maps to (part 2).
+    Csv,
+}
+
+/// How the minimum location is found in part 2.
+#[derive(PartialEq)]
+enum Algorithm {
+    /// Map whole seed ranges through the chain.
+    Ranges,
+    /// Resolve only the seeds at which the chain may change how it maps.
+    Boundary,
+    /// Resolve every single seed.
+    BruteForce,
+}
+
+/// How the input is read.
+#[derive(Clone, Copy)]
+enum InputFormat {
+    Text,
+    Json,
+}
+
+/// The answer for one of several inputs as emitted by `--output json`: the fields of `Report`,
+/// or the error that kept the input from being solved.
+#[derive(Serialize)]
+struct FileReport {
+    path: String,
+    #[serde(flatten)]
+    report: Option<Report>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The answer as emitted by `--output json`.
+#[derive(Serialize)]
+struct Report {
+    part: u8,
+    stage: String,
+    minimum_location: u64,
+    seed: u64,
+    elapsed_us: u128,
+}
+
+/// The lowest locations as emitted by `--top N --output json`.
+#[derive(Serialize)]
+struct TopReport {
+    part: u8,
+    locations: Vec<u64>,
+}
+
+/// The location printed by `--kth K --output json`.
+#[derive(Serialize)]
+struct KthReport {
+    part: u8,
+    k: u64,
+    distinct: bool,
+    location: u64,
+}
+
+/// Which seeds `--trace` prints the path of.
+enum Trace {
+    /// Every seed of the seeds line, or the start of every seed range in part 2.
+    All,
+    Seeds(Vec<u64>),
+}
+
+/// Environment variable naming the input to read when the command line has none.
+const INPUT_VARIABLE: &str = "AOC_DAY05_INPUT";
+
+/// Where every subcommand reads the almanac from.
+const INPUT_PATH: &str = "Reads stdin when PATH is `-`. Without PATH, reads the file named by the
+AOC_DAY05_INPUT environment variable, or else stdin unless it is a terminal.";
+
+/// Options taken by every subcommand, about reading the almanac and its seeds.
+const INPUT_OPTIONS: &str = "  --part 1|2                 read the seeds as values (part 1) or ranges (part 2, the default)
+  --seed N                   replace the seeds line with N, repeatable, part 1 only
+  --seed-range START:LEN     replace the seeds line with a range, repeatable, part 2 only
+  --strict                   reject maps with overlapping rules
+  --merge-duplicate-sections add the rules of a repeated section to the first one
+  --gzip                     decompress the input, implied by a `.gz` path
+  --input-format text|json   format of the input, detected when not given
+  -h, --help                 print this help";
+
+/// The first argument when it names one. The CLI started out with `solve` only, which is still
+/// what runs without a subcommand.
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Solve,
+    Trace,
+    Reverse,
+    Compose,
+    Validate,
+    Stats,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "solve" => Some(Command::Solve),
+            "trace" => Some(Command::Trace),
+            "reverse" => Some(Command::Reverse),
+            "compose" => Some(Command::Compose),
+            "validate" => Some(Command::Validate),
+            "stats" => Some(Command::Stats),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Command::Solve => "solve",
+            Command::Trace => "trace",
+            Command::Reverse => "reverse",
+            Command::Compose => "compose",
+            Command::Validate => "validate",
+            Command::Stats => "stats",
+        }
+    }
+
+    /// The usage line printed with errors about the arguments of the subcommand.
+    fn usage(self) -> &'static str {
+        match self {
+            Command::Solve => USAGE,
+            Command::Trace => "usage: aoc2023-day05 trace [--stage LABEL] [OPTIONS] PATH|- [SEED]...",
+            Command::Reverse => "usage: aoc2023-day05 reverse [--stage LABEL] [OPTIONS] PATH|- LOCATION",
+            Command::Compose => {
+                "usage: aoc2023-day05 compose [--stage LABEL] [-o FILE] [--include-identity] [OPTIONS] PATH|-"
             }
+            Command::Validate => "usage: aoc2023-day05 validate [OPTIONS] PATH|-",
+            Command::Stats => "usage: aoc2023-day05 stats [--stage LABEL] [OPTIONS] PATH|-",
         }
+    }
+
+    /// The help screen of the subcommand: its usage, what it does and the options it takes.
+    fn help(self) -> String {
+        let (description, options) = match self {
+            Command::Solve => return format!("{}\n\n{}", USAGE, HELP),
+            Command::Trace => (
+                "Prints the value of every SEED at each stage of the chain, or of every seed of the almanac
+when none is given, the start of every seed range in part 2.",
+                "  --stage LABEL              stop at the LABEL category instead of location\n",
+            ),
+            Command::Reverse => (
+                "Prints the seeds of the almanac that reach LOCATION.",
+                "  --stage LABEL              take LOCATION in the LABEL category instead of location\n",
+            ),
+            Command::Compose => (
+                "Prints the almanac with the maps of its chain composed into a single one, which resolves
+every seed to the same location.",
+                "  --stage LABEL              compose the maps up to the LABEL category only
+  -o FILE                    write the almanac to FILE instead of stdout
+  --include-identity         write a rule for the values mapped to themselves too\n",
+            ),
+            Command::Validate => (
+                "Reports overlapping rules, an odd seeds line in part 2 and a chain that does not lead from
+seeds to locations. Exits with status 1 when there is any.",
+                "",
+            ),
+            Command::Stats => (
+                "Prints how many seeds the almanac has, and how many rules every map has and how many values
+they cover.",
+                "  --stage LABEL              stop at the LABEL category instead of location\n",
+            ),
+        };
+        format!("{}\n\n{}\n\n{}\n\noptions:\n{}{}", self.usage(), description, INPUT_PATH, options, INPUT_OPTIONS)
+    }
 
-        None
+    /// Whether the subcommand takes `flag`. `solve` takes every flag.
+    fn accepts(self, flag: &str) -> bool {
+        const INPUT: [&str; 9] = [
+            "--part", "--seed", "--seed-range", "--strict", "--merge-duplicate-sections", "--gzip", "--input-format",
+            "-h", "--help",
+        ];
+        let specific: &[&str] = match self {
+            Command::Solve => return true,
+            Command::Trace | Command::Reverse | Command::Stats => &["--stage"],
+            Command::Compose => &["--stage", "-o", "--include-identity"],
+            Command::Validate => &[],
+        };
+        INPUT.contains(&flag) || specific.contains(&flag)
     }
+}
+
+/// Returns the value of a `--flag value` or `--flag=value` argument.
+fn flag_value(flag: &str, inline: Option<&str>, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    match inline {
+        Some(value) => Ok(String::from(value)),
+        None => args.next().ok_or_else(|| format!("missing value for {}", flag)),
+    }
+}
+
+/// Parses a number given as the value of `flag`.
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid number `{}` for {}", value, flag))
+}
+
+/// Parses a `START:LEN` range given as the value of `flag`.
+fn parse_range(flag: &str, value: &str) -> Result<(u64, u64), String> {
+    let (start, size) = value
+        .split_once(':')
+        .ok_or_else(|| format!("invalid range `{}` for {}, expected START:LEN", value, flag))?;
+    range_fits(flag, value, (parse_number(flag, start)?, parse_number(flag, size)?))
+}
+
+/// Fails unless the `(start, size)` range given as the value of `flag` ends within `u64`.
+fn range_fits(flag: &str, value: &str, (start, size): (u64, u64)) -> Result<(u64, u64), String> {
+    match start.checked_add(size) {
+        Some(_) => Ok((start, size)),
+        None => Err(format!("invalid range `{}` for {}, START + LEN must fit in 64 bits", value, flag)),
+    }
+}
 
-    /// This function returns a vector of new ranges from the original range passed in as a parameter.
-    fn get_ranges(&self, start: usize, size: usize) -> Vec<(usize, usize)> {
-        self.ranges
-            .iter()
-            .filter_map(|(destination, source, s_size)| {
-                let intersection_start = start.max(*source);
-                let intersection_end = (start + size).min(source + s_size);
-                if intersection_start < intersection_end {
-                    Some((
-                        destination + intersection_start - *source,
-                        intersection_end - intersection_start,
-                    ))
-                } else {
-                    None
+/// Parses a `START:LEN` or `START..END` range, END excluded, given as the value of `flag`.
+fn parse_range_or_bounds(flag: &str, value: &str) -> Result<(u64, u64), String> {
+    let invalid = |reason: &str| format!("invalid range `{}` for {}, {}", value, flag, reason);
+    let Some((start, end)) = value.split_once("..") else {
+        return match value.split_once(':').map(|(start, size)| (start.parse(), size.parse())) {
+            Some((Ok(start), Ok(size))) => range_fits(flag, value, (start, size)),
+            _ => Err(invalid("expected START:LEN or START..END")),
+        };
+    };
+    let (start, end): (u64, u64) = match (start.parse(), end.parse()) {
+        (Ok(start), Ok(end)) => (start, end),
+        _ => return Err(invalid("expected START:LEN or START..END")),
+    };
+    end.checked_sub(start).map(|size| (start, size)).ok_or_else(|| invalid("END is before START"))
+}
+
+/// Parses the command line. `default_input` is read when no input is given, and is `None` when
+/// there is nothing to fall back to.
+fn parse_args(args: impl Iterator<Item = String>, default_input: Option<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.peekable();
+    if let Some(command) = args.peek().and_then(|arg| Command::from_name(arg)) {
+        options.command = command;
+        args.next();
+    }
+    // Reported once `--help` has had the chance to win.
+    let mut rejected = None;
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        if flag.starts_with('-') && flag != "-" && !options.command.accepts(flag) {
+            rejected.get_or_insert_with(|| String::from(flag));
+        }
+        match flag {
+            "--part" => {
+                options.part = match flag_value(flag, inline, &mut args)?.as_str() {
+                    "1" => 1,
+                    "2" => 2,
+                    other => return Err(format!("invalid part `{}`, expected 1 or 2", other)),
                 }
-            })
-            .collect()
+            }
+            "--output" => {
+                options.output = match flag_value(flag, inline, &mut args)?.as_str() {
+                    "text" => Output::Text,
+                    "json" => Output::Json,
+                    "csv" => Output::Csv,
+                    other => return Err(format!("invalid output `{}`, expected text, json or csv", other)),
+                }
+            }
+            "--input-format" => {
+                options.input_format = Some(match flag_value(flag, inline, &mut args)?.as_str() {
+                    "text" => InputFormat::Text,
+                    "json" => InputFormat::Json,
+                    other => return Err(format!("invalid input format `{}`, expected text or json", other)),
+                })
+            }
+            "--seed" => options.seeds.push(parse_number(flag, &flag_value(flag, inline, &mut args)?)?),
+            "--seed-range" => options.seed_ranges.push(parse_range(flag, &flag_value(flag, inline, &mut args)?)?),
+            "--range" => options.ranges.push(parse_range_or_bounds(flag, &flag_value(flag, inline, &mut args)?)?),
+            "--explain" => {
+                for seed in flag_value(flag, inline, &mut args)?.split(',') {
+                    options.explain.push(parse_number(flag, seed)?);
+                }
+            }
+            "--reverse" => options.reverse = Some(parse_number(flag, &flag_value(flag, inline, &mut args)?)?),
+            "--queries" => options.queries = Some(flag_value(flag, inline, &mut args)?),
+            "--top" => match parse_number(flag, &flag_value(flag, inline, &mut args)?)? {
+                0 => return Err(String::from("--top requires at least 1")),
+                n => options.top = Some(n),
+            },
+            "--kth" => match parse_number(flag, &flag_value(flag, inline, &mut args)?)? {
+                0 => return Err(String::from("--kth counts from 1")),
+                k => options.kth = Some(k),
+            },
+            "--distinct" => options.distinct = true,
+            "--count-locations" => options.count_locations = true,
+            "--all" => options.all = true,
+            "--histogram" => match parse_number(flag, &flag_value(flag, inline, &mut args)?)? {
+                0 => return Err(String::from("--histogram requires a width of at least 1")),
+                width => options.histogram = Some(width),
+            },
+            "--stage" => options.stage = Some(flag_value(flag, inline, &mut args)?),
+            "--emit-dot" => options.emit_dot = Some(flag_value(flag, inline, &mut args)?),
+            "--emit-composed" => options.emit_composed = Some(flag_value(flag, inline, &mut args)?),
+            "--include-identity" => options.include_identity = true,
+            "-o" if options.command == Command::Compose => {
+                options.emit_composed = Some(flag_value(flag, inline, &mut args)?)
+            }
+            "--algorithm" => {
+                options.algorithm = match flag_value(flag, inline, &mut args)?.as_str() {
+                    "ranges" => Algorithm::Ranges,
+                    "boundary" => Algorithm::Boundary,
+                    "brute-force" => Algorithm::BruteForce,
+                    other => {
+                        return Err(format!("invalid algorithm `{}`, expected ranges, boundary or brute-force", other))
+                    }
+                }
+            }
+            "--brute-force" => options.algorithm = Algorithm::BruteForce,
+            "--verify" => options.verify = true,
+            "--sample" => match parse_number(flag, &flag_value(flag, inline, &mut args)?)? {
+                0 => return Err(String::from("--sample requires at least 1")),
+                n => options.sample = Some(n),
+            },
+            "--repl" => options.repl = true,
+            "--quiet" => options.quiet = true,
+            "--strict" => options.strict = true,
+            "--merge-duplicate-sections" => options.merge_duplicate_sections = true,
+            "--validate" => options.validate = true,
+            "--gzip" => options.gzip = true,
+            "-h" | "--help" => options.help = true,
+            "--trace" => {
+                options.trace = Some(match inline {
+                    Some(seed) => Trace::Seeds(vec![
+                        seed.parse().map_err(|_| format!("invalid seed `{}`", seed))?
+                    ]),
+                    None => Trace::All,
+                })
+            }
+            _ if arg == "-" || !arg.starts_with('-') => options.paths.push(arg),
+            _ => return Err(format!("unknown flag `{}`", flag)),
+        }
     }
+    if options.help {
+        return Ok(options);
+    }
+    if let Some(flag) = rejected {
+        return Err(format!("`{}` does not take {}", options.command.name(), flag));
+    }
+    if options.paths.is_empty() {
+        let input = default_input.ok_or("no input, give a PATH, set AOC_DAY05_INPUT or pipe the almanac to stdin")?;
+        options.paths.push(input);
+    }
+    if options.command != Command::Solve {
+        subcommand_args(&mut options)?;
+    }
+    if options.paths.len() > 1 {
+        let single = [
+            (options.validate, "--validate"),
+            (!options.explain.is_empty(), "--explain"),
+            (options.trace.is_some(), "--trace"),
+            (options.reverse.is_some(), "--reverse"),
+            (options.queries.is_some(), "--queries"),
+            (options.repl, "--repl"),
+            (options.top.is_some(), "--top"),
+            (options.kth.is_some(), "--kth"),
+            (options.count_locations, "--count-locations"),
+            (options.all, "--all"),
+            (options.histogram.is_some(), "--histogram"),
+            (options.emit_dot.is_some(), "--emit-dot"),
+            (options.emit_composed.is_some(), "--emit-composed"),
+            (matches!(options.output, Output::Csv), "--output csv"),
+        ];
+        if let Some((_, flag)) = single.iter().find(|(set, _)| *set) {
+            return Err(format!("{} takes a single input", flag));
+        }
+    }
+    if options.repl && options.paths.iter().all(|path| path == "-") {
+        return Err(String::from("--repl reads commands from stdin, the almanac must come from a file"));
+    }
+    if options.stage.is_some() && options.validate {
+        return Err(String::from("--stage does not apply to --validate"));
+    }
+    if options.include_identity && options.emit_composed.is_none() && options.command != Command::Compose {
+        return Err(String::from("--include-identity requires --emit-composed"));
+    }
+    if options.distinct && options.kth.is_none() {
+        return Err(String::from("--distinct requires --kth"));
+    }
+    if options.sample.is_some() && !options.verify {
+        return Err(String::from("--sample requires --verify"));
+    }
+    if options.strict && matches!(options.input_format, Some(InputFormat::Json)) {
+        return Err(String::from("--strict only applies to text input"));
+    }
+    if options.merge_duplicate_sections && matches!(options.input_format, Some(InputFormat::Json)) {
+        return Err(String::from("--merge-duplicate-sections only applies to text input"));
+    }
+    if options.verify && options.part == 1 {
+        return Err(String::from("--verify requires --part 2, part 1 already resolves every seed"));
+    }
+    if options.all && options.part == 1 {
+        return Err(String::from("--all requires --part 2, part 1 seeds are not ranges"));
+    }
+    if options.all && !matches!(options.output, Output::Text) {
+        return Err(String::from("--all only prints text"));
+    }
+    if !options.ranges.is_empty() {
+        if options.part == 1 {
+            return Err(String::from("--range requires --part 2, use --seed for part 1"));
+        }
+        if !options.seed_ranges.is_empty() {
+            return Err(String::from("--range and --seed-range both replace the seeds line, use one of them"));
+        }
+    }
+    if options.part == 1 && !options.seed_ranges.is_empty() {
+        return Err(String::from("--seed-range requires --part 2, use --seed for part 1"));
+    }
+    if options.part == 2 && !options.seeds.is_empty() {
+        return Err(String::from("--seed requires --part 1, use --seed-range for part 2"));
+    }
+    Ok(options)
+}
+
+/// Splits the positional arguments of a subcommand other than `solve` into its single input and
+/// what follows it, and sets the options the subcommand stands for.
+fn subcommand_args(options: &mut Options) -> Result<(), String> {
+    let command = options.command;
+    let rest = options.paths.split_off(1);
+    match command {
+        Command::Solve => unreachable!("solve takes any number of inputs"),
+        Command::Trace => {
+            let seeds = rest.iter().map(|seed| seed.parse().map_err(|_| format!("invalid seed `{}`", seed)));
+            let seeds = seeds.collect::<Result<Vec<u64>, String>>()?;
+            options.trace = Some(if seeds.is_empty() { Trace::All } else { Trace::Seeds(seeds) });
+        }
+        Command::Reverse => match &rest[..] {
+            [location] => options.reverse = Some(parse_number("reverse", location)?),
+            _ => return Err(String::from("`reverse` takes an input and a location")),
+        },
+        Command::Compose | Command::Validate | Command::Stats if !rest.is_empty() => {
+            return Err(format!("`{}` takes a single input, found `{}` after it", command.name(), rest[0]));
+        }
+        Command::Compose | Command::Stats => {}
+        Command::Validate => options.validate = true,
+    }
+    Ok(())
+}
+
+/// Opens the input, reading from `stdin` when `path` is `-` or missing and decompressing it when
+/// `gzip` is set or the path ends in `.gz`.
+fn open_input<'a>(path: Option<&str>, gzip: bool, stdin: &'a mut dyn BufRead) -> io::Result<Box<dyn BufRead + 'a>> {
+    let input: Box<dyn BufRead + 'a> = match path {
+        None | Some("-") => Box::new(stdin),
+        Some(path) => Box::new(io::BufReader::new(fs::File::open(path)?)),
+    };
+    if is_compressed(path, gzip) {
+        return Ok(Box::new(io::BufReader::new(GzDecoder::new(input))));
+    }
+    Ok(input)
+}
 
+/// Whether the input at `path` is decompressed when read.
+fn is_compressed(path: Option<&str>, gzip: bool) -> bool {
+    gzip || path.is_some_and(|path| path.ends_with(".gz"))
 }
 
-struct RangeMapChain {
-    range_maps: Vec<(String, RangeMap)>
+/// Parses the text format from `input`, or through a memory map of the file at `path` instead when
+/// the `mmap` feature is enabled and the input is a file that is not compressed.
+#[cfg_attr(not(feature = "mmap"), allow(unused_variables))]
+fn parse_text(input: Box<dyn BufRead + '_>, path: Option<&str>, options: &Options) -> Result<Almanac, ParseError> {
+    let parse_options = ParseOptions {
+        strict: options.strict,
+        merge_duplicate_sections: options.merge_duplicate_sections,
+    };
+    #[cfg(feature = "mmap")]
+    if let Some(path) = path.filter(|&path| path != "-" && !is_compressed(Some(path), options.gzip)) {
+        if let Ok(file) = fs::File::open(path) {
+            return Almanac::from_file_mmap(&file, &parse_options);
+        }
+    }
+    Almanac::from_reader_with_options(input, &parse_options)
 }
 
-impl RangeMapChain {
-    fn resolve(&self, value: usize, label: &str) -> Option<usize> {
-        let mut mapped = value;
-        for (range_map_label, range_map) in self.range_maps.iter() {
-            if let Some(output) = range_map.get(mapped) {
-                mapped = output;
-                if label == range_map_label {
-                    return Some(mapped)
+/// Problems that keep `almanac` from being solved cleanly: overlapping rules, a seeds line that
+/// cannot be read as ranges in part 2 and a chain that does not lead from seeds to locations.
+fn problems(almanac: &Almanac, part: u8) -> Vec<String> {
+    let mut problems = Vec::new();
+    let chain = &almanac.chain;
+    if chain.source(0).is_some_and(|source| source != "seed") {
+        problems.push(format!("chain starts from `{}`, expected `seed`", chain.source(0).unwrap_or_default()));
+    }
+    match chain.range_maps().last() {
+        Some((label, _)) if label != "location" => {
+            problems.push(format!("chain ends at `{}`, expected `location`", label))
+        }
+        None => problems.push(String::from("no maps")),
+        _ => {}
+    }
+    if part == 2 {
+        if let Err(e) = almanac.seed_ranges() {
+            problems.push(format!("seeds: {}", e));
+        }
+    }
+    for (label, range_map) in chain.range_maps() {
+        for overlap in range_map.validate().err().unwrap_or_default() {
+            problems.push(format!(
+                "map `{}`: rules {} and {} overlap over {}..{}",
+                label,
+                rule_text(range_map, overlap.first),
+                rule_text(range_map, overlap.second),
+                overlap.start,
+                overlap.start + overlap.size
+            ));
+        }
+    }
+    problems
+}
+
+/// Oddities `--validate` reports without failing: maps sending several sources to one value.
+fn warnings(almanac: &Almanac) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (label, range_map) in almanac.chain.range_maps() {
+        for (first, second, (start, size)) in range_map.destination_overlaps() {
+            warnings.push(format!(
+                "warning: map `{}` is not injective, rules {} and {} both map onto {}..{}",
+                label,
+                rule_text(range_map, first),
+                rule_text(range_map, second),
+                start,
+                start + size
+            ));
+        }
+    }
+    warnings
+}
+
+/// The rule at `index` of `range_map` as written in the input.
+fn rule_text(range_map: &RangeMap, index: usize) -> String {
+    let (destination, source, size) = range_map.ranges()[index];
+    format!("`{} {} {}`", destination, source, size)
+}
+
+/// Describes how `seed` goes through every map of the chain: the rule that matched, or that no
+/// rule did and the value passed through unchanged.
+fn explain(almanac: &Almanac, seed: u64) -> String {
+    let mut explanation = format!("seed {}:", seed);
+    let mut value = seed;
+    for (index, (label, range_map)) in almanac.chain.range_maps().iter().enumerate() {
+        let source = almanac.chain.source(index).unwrap_or_default();
+        let step = match range_map.get_with_rule(value) {
+            Some((mapped, rule)) => {
+                value = mapped;
+                let (destination, source, size) = range_map.ranges()[rule];
+                format!("rule #{} ({} {} {})", rule + 1, destination, source, size)
+            }
+            None => String::from("no rule, identity"),
+        };
+        explanation.push_str(&format!("\n  {}-to-{}: {} -> {}", source, label, step, value));
+    }
+    explanation
+}
+
+/// Resolves every line of the `queries` file, a bare seed or a `category value` pair, up to the
+/// last category of the chain and writes the results to `out` in order. Lines that cannot be
+/// resolved are reported to `err` and skipped. Returns whether every line was resolved.
+fn resolve_queries(
+    almanac: &Almanac,
+    path: &str,
+    queries: impl BufRead,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> io::Result<bool> {
+    let target = stage_label(almanac);
+    let mut resolved_all = true;
+    for (number, line) in queries.lines().enumerate() {
+        let resolved = line.map_err(|e| format!("could not read line: {}", e)).and_then(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (category, value) = match fields[..] {
+                [] => return Ok(None),
+                [value] => ("seed", value),
+                [category, value] => (category, value),
+                _ => return Err(format!("expected `[category] value`, found {} fields", fields.len())),
+            };
+            let value = value.parse().map_err(|_| format!("invalid number `{}`", value))?;
+            almanac.chain.resolve_between(value, category, target).map(Some).map_err(|e| e.to_string())
+        });
+        match resolved {
+            Ok(Some(location)) => writeln!(out, "{}", location)?,
+            Ok(None) => {}
+            Err(e) => {
+                writeln!(err, "error: {} line {}: {}", path, number + 1, e)?;
+                resolved_all = false;
+            }
+        }
+    }
+    Ok(resolved_all)
+}
+
+/// Commands understood by `--repl`.
+const REPL_HELP: &str = "commands:
+  seed N            resolve seed N through the whole chain
+  stage LABEL N     resolve seed N up to the LABEL category
+  trace N           show the value of seed N after every map
+  reverse N         list the seeds that resolve to location N
+  min               lowest location of the seeds of the almanac
+  help              show this list
+  quit              leave, as does end of input";
+
+/// Answers the commands read from `input` one line at a time until `quit` or the end of input.
+/// Every answer goes to `out`, errors included, so a session reads as a transcript.
+fn repl(
+    almanac: &Almanac,
+    part: u8,
+    seed_ranges: &[(u64, u64)],
+    input: impl BufRead,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let target = stage_label(almanac);
+    let prompt = |out: &mut dyn Write| {
+        write!(out, "> ")?;
+        out.flush()
+    };
+    prompt(out)?;
+    for line in input.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |word: &str| word.parse::<u64>().map_err(|_| format!("invalid number `{}`", word));
+        let answer: Result<String, String> = match words[..] {
+            [] => Ok(String::new()),
+            ["quit"] | ["exit"] => break,
+            ["help"] => Ok(String::from(REPL_HELP)),
+            ["seed", value] => number(value).and_then(|value| {
+                let resolved = almanac.chain.resolve(value, target).map_err(|e| e.to_string())?;
+                Ok(format!("{} {}", target, resolved))
+            }),
+            ["stage", label, value] => number(value).and_then(|value| {
+                let resolved = almanac.chain.resolve(value, label).map_err(|e| e.to_string())?;
+                Ok(format!("{} {}", label, resolved))
+            }),
+            ["trace", value] => number(value).map(|value| {
+                let mut line = format!("seed {}", value);
+                for (label, mapped) in almanac.chain.resolve_trace(value) {
+                    line.push_str(&format!(" -> {} {}", label, mapped));
                 }
-            } else {
-                return None
+                line
+            }),
+            ["reverse", value] => number(value).and_then(|value| {
+                let seeds = almanac.chain.resolve_reverse(value, target).map_err(|e| e.to_string())?;
+                let lines: Vec<String> = seeds.iter().map(|seed| format!("seed {} -> {} {}", seed, target, value)).collect();
+                Ok(if lines.is_empty() { format!("No seeds reach {} {}", target, value) } else { lines.join("\n") })
+            }),
+            ["min"] => {
+                let minimum = match part {
+                    1 => almanac.chain.min_location_for_seeds(&almanac.seeds),
+                    _ => almanac.chain.min_location(seed_ranges),
+                };
+                Ok(minimum.map_or(String::from("no seeds to map"), |minimum| format!("{} {}", target, minimum)))
             }
+            _ => Err(format!("unknown command `{}`, try `help`", line.trim())),
+        };
+        match answer {
+            Ok(answer) if answer.is_empty() => {}
+            Ok(answer) => writeln!(out, "{}", answer)?,
+            Err(e) => writeln!(out, "error: {}", e)?,
+        }
+        prompt(out)?;
+    }
+    writeln!(out)
+}
+
+/// Runs the brute-force search, keeping a progress line with an estimate of the time left and the
+/// best location so far up to date on `err`. The line is finished before returning so that the
+/// answer printed afterwards starts on a line of its own.
+fn brute_force_with_progress(
+    almanac: &Almanac,
+    err: &mut (dyn Write + Send),
+) -> Result<Option<(u64, u64)>, aoc2023_day05::OddSeedCount> {
+    let start = Instant::now();
+    let last_report: Mutex<(Option<Instant>, &mut (dyn Write + Send))> = Mutex::new((None, err));
+    let lowest = almanac.lowest_seed_in_ranges_brute_force_with_progress(|progress: &Progress| {
+        let (last_report, err) = &mut *last_report.lock().unwrap();
+        let finished = progress.done == progress.total;
+        if !finished && last_report.is_some_and(|last| last.elapsed() < Duration::from_millis(500)) {
+            return;
         }
-        None
+        *last_report = Some(Instant::now());
+        let fraction = progress.done as f64 / progress.total as f64;
+        let left = start.elapsed().as_secs_f64() * (1.0 - fraction) / fraction;
+        let best = progress.best.map_or(String::from("none"), |(_, location)| location.to_string());
+        // Progress is best effort, a failed write must not stop the search.
+        let _ = write!(
+            err,
+            "\rprogress: {:5.1}% of {} seeds, ETA {:.0}s, best location so far {}",
+            fraction * 100.0, progress.total, left, best
+        );
+    });
+    if let (Some(_), err) = last_report.into_inner().unwrap() {
+        let _ = writeln!(err);
     }
+    lowest
+}
+
+/// Writes the seeds and what they resolve to as CSV, with the category labels of the chain as
+/// column names.
+fn print_csv(almanac: &Almanac, part: u8, seed_ranges: &[(u64, u64)], out: &mut dyn Write) -> io::Result<()> {
+    let chain = &almanac.chain;
+    let first = chain.source(0).unwrap_or("seed");
+    let last = chain.range_maps().last().map_or(first, |(label, _)| label.as_str());
+    if part == 1 {
+        let labels: Vec<&str> = chain.range_maps().iter().map(|(label, _)| label.as_str()).collect();
+        writeln!(out, "{}", [first].iter().chain(&labels).copied().collect::<Vec<&str>>().join(","))?;
+        for &seed in &almanac.seeds {
+            let values: Vec<String> = std::iter::once(seed)
+                .chain(chain.resolve_trace(seed).into_iter().map(|(_, value)| value))
+                .map(|value| value.to_string())
+                .collect();
+            writeln!(out, "{}", values.join(","))?;
+        }
+        return Ok(());
+    }
+    writeln!(out, "{0}_start,{0}_len,{1}_start,{1}_len", first, last)?;
+    let mut rows = chain.resolve_ranges_with_origin(seed_ranges, last).unwrap_or_default();
+    rows.sort_by_key(|&(_, _, origin)| origin);
+    for (start, size, origin) in rows {
+        writeln!(out, "{},{},{},{}", origin, size, start, size)?;
+    }
+    Ok(())
+}
+
+/// Reads and parses the almanac at `path`, or `stdin` when `path` is `-` or missing, in the format
+/// given by the options or detected from the first character.
+fn read_almanac(path: Option<&str>, options: &Options, stdin: &mut dyn BufRead) -> Result<Almanac, String> {
+    let mut input = open_input(path, options.gzip, stdin)
+        .map_err(|e| format!("could not open '{}': {}", path.unwrap_or("-"), e))?;
+    let input_format = options.input_format.unwrap_or_else(|| {
+        let buffer = input.fill_buf().unwrap_or_default();
+        match buffer.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') => InputFormat::Json,
+            _ => InputFormat::Text,
+        }
+    });
+    let parsed = match input_format {
+        InputFormat::Json => Almanac::from_json_reader(input),
+        InputFormat::Text => parse_text(input, path, options),
+    };
+    let mut almanac = parsed.map_err(|e| parse_error_message(&e))?;
 
-    fn resolve_ranges(&self, ranges: &[(usize, usize)], label: &str) -> Vec<(usize, usize)> {
-        let mut mapped: Vec<(usize, usize)> = ranges.into();
-        for (range_map_label, range_map) in self.range_maps.iter() {
-            mapped = mapped.into_iter().flat_map(|(start, size)| range_map.get_ranges(start, size)).collect();
-            if label == range_map_label {
-                return mapped
+    if let Some(label) = &options.stage {
+        match almanac.chain.stage_id(label) {
+            Some(stage) => almanac.chain.truncate(stage),
+            None => {
+                let available: Vec<&str> = almanac.chain.range_maps().iter().map(|(label, _)| label.as_str()).collect();
+                return Err(format!("unknown stage '{}', available: {}", label, available.join(", ")));
             }
         }
-        mapped
     }
+    if !options.seeds.is_empty() {
+        almanac.seeds = options.seeds.clone();
+    }
+    if !options.seed_ranges.is_empty() {
+        almanac.seeds = options.seed_ranges.iter().flat_map(|&(start, size)| [start, size]).collect();
+    }
+    if !options.ranges.is_empty() {
+        let ranges = merge_ranges(options.ranges.clone());
+        almanac.seeds = ranges.into_iter().flat_map(|(start, size)| [start, size]).collect();
+    }
+    Ok(almanac)
 }
 
-#[derive(Debug)]
-enum CapturingStatus {
-    NoStatus,
-    SeedToSoil,
-    SoilToFertilizer,
-    FertilizerToWater,
-    WaterToLight,
-    LightToTemperature,
-    TemperatureToHumidity,
-    HumidityToLocation
+/// What `stats` prints: the number of seeds, how many rules every map has and how many values
+/// they cover, and the totals.
+fn stats(almanac: &Almanac) -> Vec<String> {
+    let chain = &almanac.chain;
+    let seeds = match almanac.normalized_seed_ranges() {
+        Ok(ranges) => {
+            let count: u128 = ranges.iter().map(|&(_, size)| u128::from(size)).sum();
+            format!("seeds: {} values, {} seeds as ranges", almanac.seeds.len(), count)
+        }
+        Err(_) => format!("seeds: {} values, an odd count that cannot be read as ranges", almanac.seeds.len()),
+    };
+    let mut lines = vec![seeds];
+    for (index, (label, range_map)) in chain.range_maps().iter().enumerate() {
+        let sources = match (range_map.min_source(), range_map.max_source()) {
+            (Some(min), Some(max)) => format!(", sources {} to {}", min, max),
+            _ => String::new(),
+        };
+        lines.push(format!(
+            "{}-to-{} map: {} rule(s) covering {} values{}",
+            chain.source(index).unwrap_or_default(),
+            label,
+            range_map.len(),
+            range_map.covered_size(),
+            sources
+        ));
+    }
+    let rules: usize = chain.range_maps().iter().map(|(_, range_map)| range_map.len()).sum();
+    lines.push(format!("total: {} map(s), {} rule(s)", chain.len(), rules));
+    lines
 }
 
+/// The almanac with the same seeds and a single map composed from its chain, from the first
+/// category to the last, with adjacent rules merged. Identity rules are left out unless
+/// `include_identity` is set, in which case they cover every value no other rule does.
+fn composed_almanac(almanac: &Almanac, include_identity: bool) -> Almanac {
+    let composed = almanac.chain.compose();
+    let mut composed = match include_identity {
+        true => composed.to_total(u64::MAX),
+        false => composed.without_identity(),
+    };
+    composed.normalize();
+    let first = almanac.chain.source(0).unwrap_or("seed");
+    let stage = (String::from(first), String::from(stage_label(almanac)), composed);
+    let chain = RangeMapChain::from_stages(vec![stage]).expect("Could not build the composed chain!");
+    Almanac { seeds: almanac.seeds.clone(), chain }
+}
 
-fn main() {
-    let path = env::args().nth(1).expect("Missing required parameter path!");
-
-    let mut data = io::BufReader::new(
-        fs::File::open(path).expect("Could not open file!"))
-        .lines();
-
-    let seeds: Vec<usize> = data
-        .next()
-        .expect("Unexpected EOF!")
-        .expect("Could not read line!")
-        .trim_start_matches("seeds: ")
-        .trim()
-        .split_whitespace()
-        .filter_map(|n| n.parse::<usize>().ok())
-        .collect();
-
-    let mut capturing: CapturingStatus = CapturingStatus::NoStatus;
-    let mut seed_to_soil = RangeMap::default();
-    let mut soil_to_fertilizer = RangeMap::default();
-    let mut fertilizer_to_water = RangeMap::default();
-    let mut water_to_light = RangeMap::default();
-    let mut light_to_temperature = RangeMap::default();
-    let mut temperature_to_humidity = RangeMap::default();
-    let mut humidity_to_location = RangeMap::default();
-
-    for line in data {
-        let text = line.expect("Could not read line!");
-        match text.as_str() {
-            "" => continue,
-            "seed-to-soil map:" => capturing = CapturingStatus::SeedToSoil,
-            "soil-to-fertilizer map:" => capturing = CapturingStatus::SoilToFertilizer,
-            "fertilizer-to-water map:" => capturing = CapturingStatus::FertilizerToWater,
-            "water-to-light map:" => capturing = CapturingStatus::WaterToLight,
-            "light-to-temperature map:" => capturing = CapturingStatus::LightToTemperature,
-            "temperature-to-humidity map:" => capturing = CapturingStatus::TemperatureToHumidity,
-            "humidity-to-location map:" => capturing = CapturingStatus::HumidityToLocation,
-            _ => {
-                let split: Vec<usize> = text.trim().split_whitespace().filter_map(|n| n.parse::<usize>().ok()).collect();
-                let range = (split[0], split[1], split[2]);
-                match capturing {
-                    CapturingStatus::SeedToSoil => seed_to_soil.ranges.push(range),
-                    CapturingStatus::SoilToFertilizer => soil_to_fertilizer.ranges.push(range),
-                    CapturingStatus::FertilizerToWater => fertilizer_to_water.ranges.push(range),
-                    CapturingStatus::WaterToLight => water_to_light.ranges.push(range),
-                    CapturingStatus::LightToTemperature => light_to_temperature.ranges.push(range),
-                    CapturingStatus::TemperatureToHumidity => temperature_to_humidity.ranges.push(range),
-                    CapturingStatus::HumidityToLocation => humidity_to_location.ranges.push(range),
-                    _ => ()
+/// The category the minimum is taken in: the last one of the chain, unless `--stage` truncated it.
+fn stage_label(almanac: &Almanac) -> &str {
+    almanac.chain.range_maps().last().map_or("location", |(label, _)| label)
+}
+
+/// The message of a parse error, followed by the offending line with the part the error is about
+/// underlined when it has a span.
+fn parse_error_message(error: &ParseError) -> String {
+    match &error.span {
+        Some(span) => format!(
+            "{}\n  {}\n  {}{}",
+            error,
+            error.text,
+            " ".repeat(error.text[..span.start].chars().count()),
+            "^".repeat(error.text[span.clone()].chars().count().max(1))
+        ),
+        None => error.to_string(),
+    }
+}
+
+/// Warnings about rules with overlapping source ranges, which lookups resolve arbitrarily.
+fn overlap_warnings(almanac: &Almanac) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for (label, range_map) in almanac.chain.range_maps() {
+        for overlap in range_map.validate().err().unwrap_or_default() {
+            warnings.push(format!(
+                "map `{}` has rules {} and {} overlapping over {}..{}",
+                label, overlap.first, overlap.second, overlap.start, overlap.start + overlap.size
+            ));
+        }
+    }
+    warnings
+}
+
+/// Finds the `(seed, location)` pair with the lowest location, with the algorithm of the options,
+/// verifying it when asked to. Brute-force progress goes to `err`.
+fn solve(almanac: &Almanac, options: &Options, err: &mut (dyn Write + Send)) -> Result<(u64, u64), String> {
+    let lowest = match options.part {
+        1 => almanac.lowest_seed(),
+        _ => match options.algorithm {
+            Algorithm::Ranges => almanac.lowest_seed_in_ranges(),
+            Algorithm::Boundary => almanac.lowest_seed_in_ranges_boundary(),
+            Algorithm::BruteForce if options.quiet => almanac.lowest_seed_in_ranges_brute_force(),
+            Algorithm::BruteForce => brute_force_with_progress(almanac, err),
+        }
+        .map_err(|e| e.to_string())?,
+    };
+    let (seed, minimum) = lowest.ok_or_else(|| String::from("no seeds to map"))?;
+
+    if options.verify {
+        almanac.verify_minimum(Some(minimum), options.sample).map_err(|e| format!("verification failed: {}", e))?;
+    }
+    Ok((seed, minimum))
+}
+
+/// Solves every input of the options in turn, printing one labeled line per input, or a JSON
+/// array of per-input objects. Inputs that cannot be read or solved are reported and skipped.
+/// Returns whether all of them were solved.
+fn solve_all(
+    options: &Options,
+    stdin: &mut dyn BufRead,
+    out: &mut dyn Write,
+    err: &mut (dyn Write + Send),
+) -> io::Result<bool> {
+    let mut reports = Vec::new();
+    for path in &options.paths {
+        let start = Instant::now();
+        let almanac = read_almanac(Some(path), options, stdin);
+        if let Ok(almanac) = &almanac {
+            for warning in overlap_warnings(almanac) {
+                writeln!(err, "warning: {}: {}", path, warning)?;
+            }
+        }
+        let solved = almanac.and_then(|almanac| {
+            solve(&almanac, options, err).map(|(seed, minimum)| (seed, minimum, stage_label(&almanac).to_string()))
+        });
+        let (report, error) = match solved {
+            Ok((seed, minimum, stage)) => {
+                if let Output::Text = options.output {
+                    writeln!(out, "{}: {}", path, minimum)?;
                 }
+                let report = Report {
+                    part: options.part,
+                    stage,
+                    minimum_location: minimum,
+                    seed,
+                    elapsed_us: start.elapsed().as_micros(),
+                };
+                (Some(report), None)
             }
+            Err(e) => {
+                writeln!(err, "error: {}: {}", path, e)?;
+                (None, Some(e))
+            }
+        };
+        reports.push(FileReport { path: path.clone(), report, error });
+    }
+    if let Output::Json = options.output {
+        writeln!(out, "{}", serde_json::to_string(&reports).expect("Could not serialize report!"))?;
+    }
+    Ok(reports.iter().all(|report| report.error.is_none()))
+}
+
+/// Runs the command line `args`, without the program name, reading `-` inputs and `--repl`
+/// commands from `stdin` and writing answers to `out` and diagnostics to `err`. Without an input
+/// among the arguments, the one named by `AOC_DAY05_INPUT` is read, or else `stdin` when it is not
+/// a terminal. Returns the exit status: 0 on success, 1 when the input cannot be solved and 2 for
+/// a usage error.
+fn run(
+    args: impl Iterator<Item = String>,
+    stdin: &mut dyn BufRead,
+    stdin_is_terminal: bool,
+    out: &mut dyn Write,
+    err: &mut (dyn Write + Send),
+) -> io::Result<i32> {
+    let args: Vec<String> = args.collect();
+    let default_input = match env::var(INPUT_VARIABLE) {
+        Ok(path) if !path.is_empty() => Some(path),
+        _ if !stdin_is_terminal => Some(String::from("-")),
+        _ => None,
+    };
+    let options = match parse_args(args.iter().cloned(), default_input) {
+        Ok(options) => options,
+        Err(e) => {
+            let command = args.first().and_then(|arg| Command::from_name(arg)).unwrap_or(Command::Solve);
+            writeln!(err, "error: {}\n{}", e, command.usage())?;
+            return Ok(2);
         }
+    };
+
+    if options.help {
+        writeln!(out, "{}", options.command.help())?;
+        return Ok(0);
     }
 
-    let chain = RangeMapChain{
-        range_maps: vec![
-            (
-                String::from("soil"),
-                seed_to_soil
-            ),
-            (
-                String::from("fertilizer"),
-                soil_to_fertilizer
-            ),
-            (
-                String::from("water"),
-                fertilizer_to_water
-            ),
-            (
-                String::from("light"),
-                water_to_light
-            ),
-            (
-                String::from("temperature"),
-                light_to_temperature
-            ),
-            (
-                String::from("humidity"),
-                temperature_to_humidity
-            ),
-            (
-                String::from("location"),
-                humidity_to_location
-            )
-        ]
+    if options.paths.len() > 1 {
+        if !solve_all(&options, stdin, out, err)? {
+            return Ok(1);
+        }
+        return Ok(0);
+    }
+
+    let almanac = match read_almanac(options.paths.first().map(String::as_str), &options, stdin) {
+        Ok(almanac) => almanac,
+        Err(e) => {
+            writeln!(err, "error: {}", e)?;
+            return Ok(1);
+        }
     };
 
-println!(
-    "Minimum location for seeds: {}",
-    chain.resolve_ranges(
-        &seeds
-            .chunks(2)
+    if let Some(path) = &options.emit_dot {
+        if let Err(e) = fs::write(path, to_dot(&almanac.chain)) {
+            writeln!(err, "error: could not write '{}': {}", path, e)?;
+            return Ok(1);
+        }
+    }
+    if let Some(path) = &options.emit_composed {
+        if let Err(e) = fs::write(path, composed_almanac(&almanac, options.include_identity).to_string()) {
+            writeln!(err, "error: could not write '{}': {}", path, e)?;
+            return Ok(1);
+        }
+    }
+    match options.command {
+        Command::Compose if options.emit_composed.is_none() => {
+            write!(out, "{}", composed_almanac(&almanac, options.include_identity))?;
+            return Ok(0);
+        }
+        Command::Compose => return Ok(0),
+        Command::Stats => {
+            for line in stats(&almanac) {
+                writeln!(out, "{}", line)?;
+            }
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    if options.validate {
+        let problems = problems(&almanac, options.part);
+        for warning in warnings(&almanac) {
+            writeln!(out, "{}", warning)?;
+        }
+        for problem in &problems {
+            writeln!(out, "{}", problem)?;
+        }
+        if !problems.is_empty() {
+            writeln!(out, "{} problem(s) found", problems.len())?;
+            return Ok(1);
+        }
+        let rules: usize = almanac.chain.range_maps().iter().map(|(_, range_map)| range_map.len()).sum();
+        writeln!(out, "ok: {} maps, {} rules, {} seeds", almanac.chain.len(), rules, almanac.seeds.len())?;
+        return Ok(0);
+    }
+
+    for warning in overlap_warnings(&almanac) {
+        writeln!(err, "warning: {}", warning)?;
+    }
+
+    let seed_ranges = match options.part {
+        1 => Vec::new(),
+        _ => match almanac.seed_ranges() {
+            Ok(seed_ranges) => seed_ranges,
+            Err(e) => {
+                writeln!(err, "error: {}", e)?;
+                return Ok(1);
+            }
+        },
+    };
+
+    if options.repl {
+        repl(&almanac, options.part, &seed_ranges, stdin, out)?;
+        return Ok(0);
+    }
+
+    if let Some(path) = &options.queries {
+        let queries = match fs::File::open(path) {
+            Ok(file) => io::BufReader::new(file),
+            Err(e) => {
+                writeln!(err, "error: could not open '{}': {}", path, e)?;
+                return Ok(1);
+            }
+        };
+        if !resolve_queries(&almanac, path, queries, out, err)? {
+            return Ok(1);
+        }
+        return Ok(0);
+    }
+
+    if !options.explain.is_empty() {
+        let blocks: Vec<String> = options.explain.iter().map(|&seed| explain(&almanac, seed)).collect();
+        writeln!(out, "{}", blocks.join("\n\n"))?;
+        return Ok(0);
+    }
+
+    if let Some(trace) = options.trace {
+        let seeds = match trace {
+            Trace::Seeds(seeds) => seeds,
+            Trace::All if options.part == 1 => almanac.seeds.clone(),
+            Trace::All => seed_ranges.iter().map(|&(start, _)| start).collect(),
+        };
+        for seed in seeds {
+            let mut line = format!("seed {}", seed);
+            for (label, value) in almanac.chain.resolve_trace(seed) {
+                line.push_str(&format!(" -> {} {}", label, value));
+            }
+            writeln!(out, "{}", line)?;
+        }
+        return Ok(0);
+    }
+
+    if let Some(location) = options.reverse {
+        let candidates = match almanac.chain.resolve_reverse(location, stage_label(&almanac)) {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                writeln!(err, "error: {}", e)?;
+                return Ok(1);
+            }
+        };
+        let seeds: Vec<u64> = candidates
             .into_iter()
-            .map(|s| (s[0], s[1]))
-            .collect::<Vec<(usize, usize)>>(), 
-        "location")
-        .into_iter()
-        .map(|x| x.0)
-        .min()
-        .expect("Could not map any seeds!")
-)
-
-// println!(
-//     "Minimum location for seeds: {}", 
-//     seeds
-//         .chunks(2)
-//         .into_iter()
-//         .flat_map(|range| {
-//             let start = range[0];
-//             let size = range[1];
-//             (start..(start+size))
-//                 .filter_map(|s| chain.resolve(s, "location"))
-//         })
-//         .min()
-//         .expect("Could not map any seeds!")
-// )
+            .filter(|seed| match options.part {
+                1 => almanac.seeds.contains(seed),
+                _ => seed_ranges.iter().any(|&(start, size)| start <= *seed && *seed - start < size),
+            })
+            .collect();
+        if seeds.is_empty() {
+            writeln!(out, "No seeds reach location {}", location)?;
+        }
+        for seed in seeds {
+            writeln!(out, "seed {} -> location {}", seed, location)?;
+        }
+        return Ok(0);
+    }
+
+    if let Some(n) = options.top {
+        let locations = match options.part {
+            1 => almanac.chain.lowest_locations_for_seeds(&almanac.seeds, n),
+            _ => almanac.chain.lowest_locations(&seed_ranges, n),
+        };
+        if locations.is_empty() {
+            writeln!(err, "error: no seeds to map")?;
+            return Ok(1);
+        }
+        match options.output {
+            Output::Text => {
+                let locations: Vec<String> = locations.iter().map(u64::to_string).collect();
+                writeln!(out, "Lowest {} locations for seeds: {}", locations.len(), locations.join(", "))?;
+            }
+            Output::Json => {
+                let report = TopReport { part: options.part, locations };
+                writeln!(out, "{}", serde_json::to_string(&report).expect("Could not serialize report!"))?;
+            }
+            Output::Csv => {
+                writeln!(out, "{}", stage_label(&almanac))?;
+                for location in locations {
+                    writeln!(out, "{}", location)?;
+                }
+            }
+        }
+        return Ok(0);
+    }
+
+    if options.all {
+        let target = stage_label(&almanac);
+        let mut minimum = None;
+        let mut resolver = Resolver::new();
+        for &(start, size) in &seed_ranges {
+            let resolved =
+                almanac.chain.resolve_ranges_with(&[(start, size)], target, &mut resolver).unwrap_or_default();
+            match resolved.first() {
+                Some(&(location, _)) => {
+                    writeln!(out, "range {}+{} -> min {} {}", start, size, target, location)?;
+                    minimum = Some(minimum.map_or(location, |minimum: u64| minimum.min(location)));
+                }
+                None => writeln!(out, "range {}+{} -> no seeds", start, size)?,
+            }
+        }
+        match minimum {
+            Some(minimum) => writeln!(out, "Minimum {} for seeds: {}", target, minimum)?,
+            None => {
+                writeln!(err, "error: no seeds to map")?;
+                return Ok(1);
+            }
+        }
+        return Ok(0);
+    }
+
+    if options.count_locations {
+        let count = match options.part {
+            1 => almanac.chain.count_locations(&almanac.seeds.iter().map(|&seed| (seed, 1)).collect::<Vec<_>>()),
+            _ => almanac.chain.count_locations(&seed_ranges),
+        };
+        writeln!(out, "Distinct {}s for seeds: {}", stage_label(&almanac), count)?;
+        return Ok(0);
+    }
+
+    if let Some(width) = options.histogram {
+        let seed_ranges = match options.part {
+            1 => almanac.seeds.iter().map(|&seed| (seed, 1)).collect(),
+            _ => seed_ranges,
+        };
+        for (bucket, count) in almanac.chain.location_histogram(&seed_ranges, width) {
+            writeln!(out, "{} {}", bucket, count)?;
+        }
+        return Ok(0);
+    }
+
+    if let Some(k) = options.kth {
+        // Part 1 seeds are ranges of a single seed.
+        let seed_ranges = match options.part {
+            1 => almanac.seeds.iter().map(|&seed| (seed, 1)).collect(),
+            _ => seed_ranges,
+        };
+        let location = match options.distinct {
+            true => almanac.chain.kth_distinct_location(&seed_ranges, k),
+            false => almanac.chain.kth_location(&seed_ranges, k),
+        };
+        let Some(location) = location else {
+            let counted = if options.distinct { "distinct location" } else { "seed" };
+            writeln!(err, "error: fewer than {} {}s reached", k, counted)?;
+            return Ok(1);
+        };
+        match options.output {
+            Output::Text => writeln!(out, "Lowest location #{} for seeds: {}", k, location)?,
+            Output::Json => {
+                let report = KthReport { part: options.part, k, distinct: options.distinct, location };
+                writeln!(out, "{}", serde_json::to_string(&report).expect("Could not serialize report!"))?;
+            }
+            Output::Csv => writeln!(out, "{}\n{}", stage_label(&almanac), location)?,
+        }
+        return Ok(0);
+    }
+
+    if let Output::Csv = options.output {
+        print_csv(&almanac, options.part, &seed_ranges, out)?;
+        return Ok(0);
+    }
+
+    let start = Instant::now();
+    let (seed, minimum) = match solve(&almanac, &options, err) {
+        Ok(lowest) => lowest,
+        Err(e) => {
+            writeln!(err, "error: {}", e)?;
+            return Ok(1);
+        }
+    };
+
+    match options.output {
+        Output::Text => {
+            writeln!(out, "Minimum {} for seeds: {}", stage_label(&almanac), minimum)?;
+            match seed_ranges.iter().find(|&&(start, size)| start <= seed && seed - start < size) {
+                Some(&(range_start, _)) if options.part == 2 => {
+                    writeln!(out, "Reached from seed {} (input range starting at {})", seed, range_start)?
+                }
+                _ => writeln!(out, "Reached from seed {}", seed)?,
+            }
+        }
+        Output::Json => {
+            let report = Report {
+                part: options.part,
+                stage: stage_label(&almanac).to_string(),
+                minimum_location: minimum,
+                seed,
+                elapsed_us: start.elapsed().as_micros(),
+            };
+            writeln!(out, "{}", serde_json::to_string(&report).expect("Could not serialize report!"))?;
+        }
+        Output::Csv => unreachable!("CSV output is written before solving"),
+    }
+    Ok(0)
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let stdin_is_terminal = stdin.is_terminal();
+    let status = run(
+        env::args().skip(1),
+        &mut stdin.lock(),
+        stdin_is_terminal,
+        &mut io::stdout().lock(),
+        &mut io::stderr(),
+    )
+    .expect("Could not write output!");
+    process::exit(status);
+}
+
+/// The sample almanac of the puzzle, fed to `run` as stdin by the snapshot tests.
+#[cfg(test)]
+const EXAMPLE: &str = include_str!("../tests/data/example.txt");
+
+/// Runs `args` with `input` as stdin, returning the exit status and what was written to stdout and
+/// stderr.
+#[cfg(test)]
+fn run_captured(args: &[&str], input: &str) -> (i32, String, String) {
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let args = args.iter().map(|arg| String::from(*arg));
+    let status = run(args, &mut input.as_bytes(), false, &mut out, &mut err).unwrap();
+    (status, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
 }
 
+/// Compares `actual` with the golden file `tests/snapshots/<name>`. Setting `UPDATE_SNAPSHOTS`
+/// writes `actual` to the file instead, to record a new snapshot or accept a changed one.
+#[cfg(test)]
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(name);
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("could not read snapshot {}: {}, run with UPDATE_SNAPSHOTS=1", path.display(), e));
+    assert_eq!(expected, actual, "output differs from snapshot {}", path.display());
+}
 
 #[test]
-fn test_resolve_range() {
-    let range_map = RangeMap {
-        ranges: vec![(100, 0, 50), (200, 50, 50), (500, 100, 100)]
-    };
+fn test_snapshot_text() {
+    for (name, part) in [("text_part1.txt", "1"), ("text_part2.txt", "2")] {
+        let (status, out, err) = run_captured(&["-", "--part", part], EXAMPLE);
+        assert_eq!((0, ""), (status, err.as_str()));
+        assert_snapshot(name, &out);
+    }
+}
+
+#[test]
+fn test_snapshot_trace() {
+    for (name, part) in [("trace_part1.txt", "1"), ("trace_part2.txt", "2")] {
+        let (status, out, err) = run_captured(&["-", "--part", part, "--trace"], EXAMPLE);
+        assert_eq!((0, ""), (status, err.as_str()));
+        assert_snapshot(name, &out);
+    }
+}
+
+#[test]
+fn test_snapshot_json() {
+    for (name, part) in [("json_part1.txt", "1"), ("json_part2.txt", "2")] {
+        let (status, out, err) = run_captured(&["-", "--part", part, "--output", "json"], EXAMPLE);
+        assert_eq!((0, ""), (status, err.as_str()));
+        // The time taken changes from run to run.
+        let (before, after) = out.split_once("\"elapsed_us\":").unwrap();
+        let after = after.trim_start_matches(|c: char| c.is_ascii_digit());
+        assert_snapshot(name, &format!("{}\"elapsed_us\":0{}", before, after));
+    }
+}
+
+#[test]
+fn test_snapshot_csv() {
+    for (name, part) in [("csv_part1.txt", "1"), ("csv_part2.txt", "2")] {
+        let (status, out, err) = run_captured(&["-", "--part", part, "--output", "csv"], EXAMPLE);
+        assert_eq!((0, ""), (status, err.as_str()));
+        assert_snapshot(name, &out);
+    }
+}
 
-    assert_eq!(vec![(125, 25), (200, 25)], range_map.get_ranges(25, 50));
-    assert_eq!(vec![(110, 10)], range_map.get_ranges(10, 10));
-}
\ No newline at end of file
+#[test]
+fn test_snapshot_malformed_input() {
+    let (status, out, err) = run_captured(&["-"], include_str!("../tests/data/malformed.txt"));
+    assert_eq!((1, ""), (status, out.as_str()));
+    assert_snapshot("malformed_stderr.txt", &err);
+}
+
+#[test]
+fn test_snapshot_verify_mismatch() {
+    // Seeds 3 and 4 sit where the two rules overlap: the range algorithm maps them through the
+    // first rule, resolving them one at a time picks the second.
+    let input = include_str!("../tests/data/overlapping.txt").replace("seeds: 1 2", "seeds: 3 2");
+    let (status, out, err) = run_captured(&["-", "--verify"], &input);
+    assert_eq!((1, ""), (status, out.as_str()));
+    assert_snapshot("verify_mismatch_stderr.txt", &err);
+}
+
+#[test]
+fn test_default_input() {
+    let args = |args: &[&str]| args.iter().map(|arg| String::from(*arg)).collect::<Vec<String>>().into_iter();
+    let paths = |args, default_input| parse_args(args, default_input).map(|options| options.paths);
+
+    assert_eq!(Ok(vec![String::from("a.txt")]), paths(args(&["a.txt"]), Some(String::from("b.txt"))));
+    assert_eq!(Ok(vec![String::from("b.txt")]), paths(args(&["--part", "1"]), Some(String::from("b.txt"))));
+    assert_eq!(Ok(vec![String::from("-")]), paths(args(&["stats"]), Some(String::from("-"))));
+    // Nothing to fall back to, as when stdin is a terminal.
+    let error = paths(args(&["--part", "1"]), None).unwrap_err();
+    assert_eq!("no input, give a PATH, set AOC_DAY05_INPUT or pipe the almanac to stdin", error);
+    assert!(paths(args(&["--help"]), None).is_ok());
+}
@@ -0,0 +1,747 @@
+use std::io::{self, BufRead, Read};
+use std::ops::Range;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::{ChainBuilder, MapValue, ParseError, ParseErrorKind, RangeMap, RangeMapChain};
+
+/// Returns the `<from>` and `<to>` categories of a `<from>-to-<to> map:` section header. Any amount
+/// of whitespace is accepted before `map:`.
+fn section_label(line: &str) -> Option<(&str, &str)> {
+    let header = line.strip_suffix("map:")?;
+    if !header.ends_with(char::is_whitespace) {
+        return None;
+    }
+    header.trim_end().split_once("-to-")
+}
+
+/// Whether a trimmed line carries nothing to parse: blank lines and `#` comments.
+fn is_ignored(text: &str) -> bool {
+    text.is_empty() || text.starts_with('#')
+}
+
+/// Byte range of `token`, a slice of `text`, within `text`.
+fn span_of(text: &str, token: &str) -> Range<usize> {
+    let start = token.as_ptr() as usize - text.as_ptr() as usize;
+    start..start + token.len()
+}
+
+/// Parses every whitespace separated token of `text` as a number.
+fn parse_numbers<T: MapValue>(text: &str, line: usize) -> Result<Vec<T>, ParseError> {
+    text.split_whitespace()
+        .map(|n| n.parse::<T>().map_err(|_| {
+            ParseError::new(line, text, ParseErrorKind::InvalidNumber(String::from(n))).with_span(span_of(text, n))
+        }))
+        .collect()
+}
+
+/// Parses a `destination source size` rule line of the `section` map, named in errors.
+fn parse_rule<T: MapValue>(text: &str, line: usize, section: Option<&str>) -> Result<(T, T, T), ParseError> {
+    let split: Vec<T> = parse_numbers(text, line)?;
+    if split.len() != 3 {
+        // Point at the extra numbers, or at the whole line when some are missing.
+        let span = match text.split_whitespace().nth(3) {
+            Some(extra) => span_of(text, extra).start..text.len(),
+            None => 0..text.len(),
+        };
+        let kind = ParseErrorKind::WrongFieldCount { found: split.len(), section: section.map(String::from) };
+        return Err(ParseError::new(line, text, kind).with_span(span));
+    }
+    check_rule((split[0], split[1], split[2])).map_err(|kind| ParseError::new(line, text, kind))
+}
+
+/// Checks a `(destination, source, size)` rule read as `T`, however it was read: its source and
+/// destination intervals must both end within `T`. Empty rules are accepted.
+pub(crate) fn check_rule<T: MapValue>(rule: (T, T, T)) -> Result<(T, T, T), ParseErrorKind> {
+    let (destination, source, size) = rule;
+    if source.checked_add(size).is_none() || destination.checked_add(size).is_none() {
+        return Err(ParseErrorKind::RuleOverflow { bits: T::BITS });
+    }
+    Ok(rule)
+}
+
+/// Parses a single map block: an optional `<from>-to-<to> map:` header followed by one rule per
+/// line. Blank lines and `#` comments are ignored and line numbers in errors are relative to the
+/// block. Numbers are read as `T`, so a rule that does not fit it is an error.
+impl<T: MapValue> FromStr for RangeMap<T> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut range_map = RangeMap::default();
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(index, text)| (index + 1, text.trim()))
+            .filter(|(_, text)| !is_ignored(text));
+        let mut first = lines.next();
+        let mut section = None;
+        if let Some((_, text)) = first {
+            if section_label(text).is_some() {
+                section = text.strip_suffix("map:").map(str::trim_end);
+                first = None;
+            }
+        }
+        for (line, text) in first.into_iter().chain(lines) {
+            range_map.push(parse_rule(text, line, section)?);
+        }
+        Ok(range_map)
+    }
+}
+
+/// How strictly `Almanac::from_reader_with_options` reads the text format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Reject sections with overlapping rules.
+    pub strict: bool,
+    /// Add the rules of a section header seen again to the section it repeats instead of
+    /// rejecting it, as long as they do not overlap the rules already there.
+    pub merge_duplicate_sections: bool,
+}
+
+/// Like `parse_with_options`, rejecting overlapping rules when `strict` is set.
+pub(crate) fn parse<R: BufRead>(reader: R, strict: bool) -> Result<(Vec<u64>, RangeMapChain), ParseError> {
+    parse_with_options(reader, &ParseOptions { strict, ..ParseOptions::default() })
+}
+
+/// Parses an almanac from `reader` as `parse_lines` does.
+pub(crate) fn parse_with_options<R: BufRead>(
+    reader: R,
+    options: &ParseOptions
+) -> Result<(Vec<u64>, RangeMapChain), ParseError> {
+    let lines = reader.lines().enumerate().map(|(index, line)| {
+        line.map(|text| (index + 1, String::from(trim_line(index, &text))))
+            .map_err(|e| ParseError::new(index + 1, "", ParseErrorKind::Io(e)))
+    });
+    parse_lines(lines, options)
+}
+
+/// Parses an almanac from its whole text as `parse_lines` does, without allocating a string per
+/// line. Bytes that are not UTF-8 fail the line they are on, as they do when read from a reader.
+pub(crate) fn parse_bytes(
+    bytes: &[u8],
+    options: &ParseOptions
+) -> Result<(Vec<u64>, RangeMapChain), ParseError> {
+    // A last line ending in a newline is not followed by an empty one, as with `BufRead::lines`.
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let lines = bytes.split(|&byte| byte == b'\n').enumerate().map(|(index, text)| {
+        std::str::from_utf8(text).map(|text| (index + 1, trim_line(index, text))).map_err(|_| {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8");
+            ParseError::new(index + 1, "", ParseErrorKind::Io(e))
+        })
+    });
+    parse_lines(lines, options)
+}
+
+/// The text of the line at `index` as parsed: trimmed, which also drops the `\r` of CRLF line
+/// endings, and without the byte order mark the first line may start with.
+fn trim_line(index: usize, text: &str) -> &str {
+    match index {
+        0 => text.trim_start_matches('\u{feff}').trim(),
+        _ => text.trim(),
+    }
+}
+
+/// Parses an almanac from its trimmed, numbered lines, returning the raw numbers of the seeds line
+/// and a chain with one map per `<from>-to-<to> map:` section. The sections may come in any order
+/// as long as they form a single path between categories, see `section_order`. A section header
+/// may only appear once, unless `options` merges repeated sections. When `options` is strict,
+/// sections with overlapping rules are rejected. Blank lines and `#` comments are skipped anywhere.
+/// The seeds may continue on the lines after `seeds:`, up to the first blank line or section
+/// header, and there must be at least one.
+fn parse_lines<L: AsRef<str>>(
+    lines: impl Iterator<Item = Result<(usize, L), ParseError>>,
+    options: &ParseOptions
+) -> Result<(Vec<u64>, RangeMapChain), ParseError> {
+    let mut data = lines.filter(|entry| !matches!(entry, Ok((_, text)) if text.as_ref().starts_with('#')));
+
+    let (line, text) = data
+        .find(|entry| !matches!(entry, Ok((_, text)) if text.as_ref().is_empty()))
+        .ok_or_else(|| ParseError::new(1, "", ParseErrorKind::UnexpectedEof))??;
+    let text = text.as_ref();
+    if section_label(text).is_some() {
+        return Err(ParseError::new(line, text, ParseErrorKind::MissingSeedsLine));
+    }
+    let numbers = text.trim_start_matches("seeds:");
+    let prefix = text.len() - numbers.len();
+    let mut seeds = parse_numbers::<u64>(numbers, line).map_err(|e| ParseError {
+        span: e.span.map(|span| span.start + prefix..span.end + prefix),
+        text: String::from(text),
+        ..e
+    })?;
+    // The lines holding seeds, and the index of the line and the span of every seed in them.
+    let mut seed_lines = vec![(line, String::from(text))];
+    let mut seed_spans: Vec<(usize, Range<usize>)> =
+        numbers.split_whitespace().map(|n| (0, span_of(text, n))).collect();
+    let mut capturing_seeds = true;
+
+    let mut range_maps: Vec<(String, String, RangeMap)> = Vec::new();
+    let mut headers: Vec<(usize, String)> = Vec::new();
+    // The section rules are added to, and the sections merged with a repeated header.
+    let mut current: Option<usize> = None;
+    let mut merged: Vec<(usize, usize, String)> = Vec::new();
+
+    for entry in data {
+        let (line, text) = entry?;
+        let text = text.as_ref();
+        if capturing_seeds {
+            if text.starts_with(|c: char| c.is_ascii_digit()) {
+                seeds.extend(parse_numbers::<u64>(text, line)?);
+                seed_spans.extend(text.split_whitespace().map(|n| (seed_lines.len(), span_of(text, n))));
+                seed_lines.push((line, String::from(text)));
+                continue;
+            }
+            // Blank lines right after a bare `seeds:` line do not end the seeds yet.
+            capturing_seeds = text.is_empty() && seeds.is_empty();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        if let Some((source, label)) = section_label(text) {
+            let repeated = range_maps.iter().position(|(other_source, other_label, _)| {
+                other_source == source && other_label == label
+            });
+            match repeated {
+                Some(index) if options.merge_duplicate_sections => {
+                    current = Some(index);
+                    merged.push((index, line, String::from(text)));
+                }
+                Some(index) => {
+                    let kind = ParseErrorKind::DuplicateSection { first_line: headers[index].0 };
+                    return Err(ParseError::new(line, text, kind));
+                }
+                None => {
+                    current = Some(range_maps.len());
+                    range_maps.push((String::from(source), String::from(label), RangeMap::default()));
+                    headers.push((line, String::from(text)));
+                }
+            }
+            continue;
+        }
+        if !text.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(ParseError::new(line, text, ParseErrorKind::UnknownHeader));
+        }
+        let section = current.and_then(|index| headers[index].1.strip_suffix("map:")).map(str::trim_end);
+        let range = parse_rule(text, line, section)?;
+        match current {
+            Some(index) => range_maps[index].2.push(range),
+            None => return Err(ParseError::new(line, text, ParseErrorKind::RuleOutsideSection)),
+        }
+    }
+
+    if seeds.is_empty() {
+        let (line, text) = &seed_lines[0];
+        return Err(ParseError::new(*line, text, ParseErrorKind::NoSeeds));
+    }
+    for (pair, spans) in seeds.chunks_exact(2).zip(seed_spans.chunks_exact(2)) {
+        if pair[0].checked_add(pair[1]).is_none() {
+            let ((start_line, start), (size_line, size)) = (&spans[0], &spans[1]);
+            let (line, text) = &seed_lines[*start_line];
+            let end = if start_line == size_line { size.end } else { text.len() };
+            return Err(ParseError::new(*line, text, ParseErrorKind::SeedRangeOverflow).with_span(start.start..end));
+        }
+    }
+
+    for (index, line, text) in merged {
+        range_maps[index]
+            .2
+            .validate()
+            .map_err(|overlaps| ParseError::new(line, &text, ParseErrorKind::OverlappingRules(overlaps)))?;
+    }
+
+    if options.strict {
+        for ((_, _, range_map), (line, text)) in range_maps.iter().zip(headers.iter()) {
+            range_map
+                .validate()
+                .map_err(|overlaps| ParseError::new(*line, text, ParseErrorKind::OverlappingRules(overlaps)))?;
+        }
+    }
+
+    let order = section_order(&range_maps, &headers)?;
+    let start = order.first().map_or(String::from("seed"), |&index| range_maps[index].0.clone());
+    let headers: Vec<(usize, String)> = order.iter().map(|&index| headers[index].clone()).collect();
+    let mut sections: Vec<Option<(String, String, RangeMap)>> = range_maps.into_iter().map(Some).collect();
+    let builder = order
+        .iter()
+        .filter_map(|&index| sections[index].take())
+        .fold(ChainBuilder::new(&start), |builder, (source, label, range_map)| {
+            builder.stage_from(&source, &label, range_map)
+        });
+    let chain = builder.build().map_err(|e| {
+        let (line, text) = &headers[e.index()];
+        ParseError::new(*line, text, ParseErrorKind::InvalidChain(e))
+    })?;
+    Ok((seeds, chain))
+}
+
+/// Orders the `(source, label, map)` sections, whose headers are in `headers`, into a path from
+/// the start category to the last one, returning their indices in chain order. The start is
+/// `seed` when no section maps to it, or else the first category in file order that no section
+/// maps to. Fails if two sections map from the same category, if a section maps back to a category
+/// already on the path, or if a section is not on the path at all.
+fn section_order(
+    sections: &[(String, String, RangeMap)],
+    headers: &[(usize, String)]
+) -> Result<Vec<usize>, ParseError> {
+    for (index, (source, _, _)) in sections.iter().enumerate() {
+        if let Some(first) = sections[..index].iter().position(|(other, _, _)| other == source) {
+            let (line, text) = &headers[index];
+            let kind = ParseErrorKind::BranchingSection { category: source.clone(), first_line: headers[first].0 };
+            return Err(ParseError::new(*line, text, kind));
+        }
+    }
+
+    let mut starts = sections
+        .iter()
+        .map(|(source, _, _)| source)
+        .filter(|&source| sections.iter().all(|(_, label, _)| label != source));
+    let start = match starts.clone().find(|&source| source == "seed") {
+        Some(seed) => seed,
+        None => match starts.next().or_else(|| sections.first().map(|(source, _, _)| source)) {
+            Some(start) => start,
+            None => return Ok(Vec::new()),
+        },
+    };
+
+    let mut order = Vec::new();
+    let mut reached = vec![start];
+    while let Some(index) = sections.iter().position(|(source, _, _)| source == reached[reached.len() - 1]) {
+        let label = &sections[index].1;
+        if reached.contains(&label) {
+            let (line, text) = &headers[index];
+            return Err(ParseError::new(*line, text, ParseErrorKind::SectionCycle { category: label.clone() }));
+        }
+        order.push(index);
+        reached.push(label);
+    }
+
+    if let Some(index) = (0..sections.len()).find(|index| !order.contains(index)) {
+        let (line, text) = &headers[index];
+        return Err(ParseError::new(*line, text, ParseErrorKind::UnreachableSection { start: start.clone() }));
+    }
+    Ok(order)
+}
+
+/// One `{from, to, rules}` entry of the `maps` array of a JSON almanac.
+#[derive(Deserialize)]
+struct JsonMap {
+    from: String,
+    to: String,
+    rules: Vec<(u64, u64, u64)>,
+}
+
+/// A JSON almanac as written by other tools: a `seeds` array and an ordered `maps` array.
+#[derive(Deserialize)]
+struct JsonAlmanac {
+    seeds: Vec<u64>,
+    maps: Vec<JsonMap>,
+}
+
+/// A JSON almanac checked the same way as the text format, so that errors carry the position in
+/// the JSON input.
+#[derive(Deserialize)]
+#[serde(try_from = "JsonAlmanac")]
+struct ParsedJson(Vec<u64>, RangeMapChain);
+
+impl TryFrom<JsonAlmanac> for ParsedJson {
+    type Error = ParseErrorKind;
+
+    fn try_from(almanac: JsonAlmanac) -> Result<Self, Self::Error> {
+        if almanac.seeds.is_empty() {
+            return Err(ParseErrorKind::NoSeeds);
+        }
+        if almanac.seeds.chunks_exact(2).any(|pair| pair[0].checked_add(pair[1]).is_none()) {
+            return Err(ParseErrorKind::SeedRangeOverflow);
+        }
+        let start = almanac.maps.first().map_or(String::from("seed"), |map| map.from.clone());
+        let mut builder = ChainBuilder::new(&start);
+        for map in almanac.maps {
+            for &rule in &map.rules {
+                check_rule(rule)?;
+            }
+            builder = builder.stage_from(&map.from, &map.to, RangeMap::new(map.rules));
+        }
+        let chain = builder.build().map_err(ParseErrorKind::InvalidChain)?;
+        Ok(Self(almanac.seeds, chain))
+    }
+}
+
+/// Parses a JSON almanac of the form
+/// `{"seeds": [..], "maps": [{"from": "seed", "to": "soil", "rules": [[d, s, n], ..]}, ..]}`.
+/// Errors are reported at the line of the JSON input where they were detected.
+pub(crate) fn parse_json<R: Read>(reader: R) -> Result<(Vec<u64>, RangeMapChain), ParseError> {
+    let ParsedJson(seeds, chain) = serde_json::from_reader(reader)
+        .map_err(|e| ParseError::new(e.line(), "", ParseErrorKind::Json(e)))?;
+    Ok((seeds, chain))
+}
+
+#[test]
+fn test_parse_custom_categories() {
+    let text = "seeds: 1 7\n\na-to-b map:\n10 0 5\n\nb-to-c map:\n0 12 3\n";
+    let (seeds, chain) = parse(text.as_bytes(), false).unwrap();
+
+    assert_eq!(vec![1, 7], seeds);
+    assert_eq!(
+        vec!["b", "c"],
+        chain.range_maps().iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>()
+    );
+    assert_eq!(Ok(11), chain.resolve(1, "b"));
+    assert_eq!(Ok(1), chain.resolve(3, "c"));
+    assert_eq!(Ok(7), chain.resolve(7, "c"));
+    assert_eq!(Some("a"), chain.source(0));
+    assert_eq!(Some("b"), chain.source(1));
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = parse("seeds: 1 2\n\na-to-b map:\n10 0\n".as_bytes(), false).unwrap_err();
+    assert_eq!(4, error.line);
+    assert_eq!("10 0", error.text);
+    assert_eq!("line 4: expected 3 numbers in a rule of section `a-to-b`, found 2", error.to_string());
+
+    let error = parse("seeds: 1 2\na-to-b map:\n10 0 5 7\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 3: expected 3 numbers in a rule of section `a-to-b`, found 4", error.to_string());
+
+    let error = parse("seeds: 1 2\na-to-b map:\n10 0 5\nthis is garbage\n".as_bytes(), false).unwrap_err();
+    assert_eq!("this is garbage", error.text);
+    assert_eq!("line 4: expected a `<from>-to-<to> map:` section header", error.to_string());
+
+    let error = parse("seeds: 1 2\na-to-b map:\n10 zero 5\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 3: invalid number `zero`", error.to_string());
+
+    let error = parse("seeds: 1 x\n".as_bytes(), false).unwrap_err();
+    assert_eq!("seeds: 1 x", error.text);
+    assert_eq!("line 1: invalid number `x`", error.to_string());
+
+    let error = parse("".as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn test_parse_error_kinds() {
+    let parse_error = |text: &str| parse(text.as_bytes(), false).unwrap_err();
+
+    let error = parse_error("seeds: 1 2\n\na-to-b map:\n10 0\n");
+    assert!(matches!(error.kind, ParseErrorKind::WrongFieldCount { found: 2, .. }));
+    assert_eq!((4, Some(0..4)), (error.line, error.span));
+
+    let error = parse_error("seeds: 1 2\na-to-b map:\n10  0 5 7 8\n");
+    assert!(matches!(error.kind, ParseErrorKind::WrongFieldCount { found: 5, .. }));
+    assert_eq!((3, Some(8..11)), (error.line, error.span));
+
+    let error = parse_error("seeds: 1 2\na-to-b map:\n10 zero 5\n");
+    assert!(matches!(&error.kind, ParseErrorKind::InvalidNumber(token) if token == "zero"));
+    assert_eq!((3, Some(3..7)), (error.line, error.span));
+
+    let error = parse_error("seeds:  1 x\n");
+    assert!(matches!(&error.kind, ParseErrorKind::InvalidNumber(token) if token == "x"));
+    assert_eq!((1, Some(10..11)), (error.line, error.span.clone()));
+    assert_eq!("x", &error.text[10..11]);
+
+    let error = parse_error("seeds: 1 2\na-to-b map:\n10 0 5\nb-to-c mop:\n");
+    assert!(matches!(error.kind, ParseErrorKind::UnknownHeader));
+    assert_eq!((4, None), (error.line, error.span));
+
+    let error = parse_error("seeds: 1 2\n\n5 0 5\n");
+    assert!(matches!(error.kind, ParseErrorKind::RuleOutsideSection));
+    assert_eq!(3, error.line);
+
+    let error = parse_error(&format!("seeds: 1 2\na-to-b map:\n0 {} 2\n", u64::MAX));
+    assert!(matches!(error.kind, ParseErrorKind::RuleOverflow { .. }));
+    assert_eq!(3, error.line);
+
+    let error = parse_error("# nothing but a comment\n\n");
+    assert!(matches!(error.kind, ParseErrorKind::UnexpectedEof));
+    assert_eq!(1, error.line);
+}
+
+#[test]
+fn test_parse_wide_range_map() {
+    let big = u128::from(u64::MAX) + 1;
+    let text = format!("seed-to-soil map:\n{} {} 10\n", big * 2, big);
+    let range_map: RangeMap<u128> = text.parse().unwrap();
+    assert_eq!(Some(big * 2 + 3), range_map.get(big + 3));
+
+    let error = text.parse::<RangeMap<u64>>().unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::InvalidNumber(_)));
+    let error = format!("{} 0 2\n", u64::MAX).parse::<RangeMap<u64>>().unwrap_err();
+    assert_eq!("line 1: rule overflows, source + size and destination + size must fit in 64 bits", error.to_string());
+    assert!(format!("{} 0 2\n", u64::MAX).parse::<RangeMap<u128>>().is_ok());
+}
+
+#[test]
+fn test_parse_rule_field_count() {
+    let almanac = |rule: &str| format!("seeds: 1 2\n\nseed-to-soil map:\n50 98 2\n\nsoil-to-location map:\n{}\n", rule);
+    for (rule, found) in [("10 20", 2), ("10 20 5 6", 4), ("1", 1), ("10\t20   5 6 7", 5)] {
+        let error = parse(almanac(rule).as_bytes(), false).unwrap_err();
+        assert_eq!(7, error.line, "{:?}", rule);
+        let ParseErrorKind::WrongFieldCount { found: actual, section: Some(section) } = &error.kind else {
+            panic!("{:?}", error.kind);
+        };
+        assert_eq!((found, "soil-to-location"), (*actual, section.as_str()));
+    }
+    // Surrounding and repeated whitespace is not a field.
+    let (_, chain) = parse(almanac("  10 \t 20   5  ").as_bytes(), false).unwrap();
+    assert_eq!(&[(10, 20, 5)], chain.range_maps()[1].1.ranges());
+}
+
+#[test]
+fn test_parse_missing_seeds() {
+    let rules = "seed-to-soil map:\n50 98 2\n";
+    for seeds in ["seeds:\n\n", "seeds:   \n", "# seeds to come\nseeds:\n\n\n"] {
+        let error = parse(format!("{}{}", seeds, rules).as_bytes(), false).unwrap_err();
+        assert!(matches!(error.kind, ParseErrorKind::NoSeeds), "{:?}", seeds);
+        assert_eq!("seeds:", error.text);
+        assert_eq!(format!("line {}: no seeds found", error.line), error.to_string());
+    }
+    assert_eq!(1, parse(format!("seeds:\n{}", rules).as_bytes(), false).unwrap_err().line);
+    assert!(matches!(parse("seeds:".as_bytes(), false).unwrap_err().kind, ParseErrorKind::NoSeeds));
+
+    let error = parse(format!("\n{}", rules).as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::MissingSeedsLine));
+    assert_eq!("line 2: expected a `seeds:` line before the first section", error.to_string());
+
+    let error = parse_json(r#"{"seeds": [], "maps": []}"#.as_bytes()).unwrap_err();
+    assert!(error.to_string().contains("no seeds found"), "{}", error);
+}
+
+#[test]
+fn test_parse_duplicate_section() {
+    let text = "seeds: 1 2\n\na-to-b map:\n10 0 5\n\nb-to-c map:\n0 12 3\n\na-to-b map:\n20 5 5\n";
+    let error = parse(text.as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::DuplicateSection { first_line: 3 }));
+    assert_eq!("line 9: section already started on line 3", error.to_string());
+    assert_eq!("a-to-b map:", error.text);
+
+    let merge = ParseOptions { merge_duplicate_sections: true, ..ParseOptions::default() };
+    let (_, chain) = parse_with_options(text.as_bytes(), &merge).unwrap();
+    assert_eq!(2, chain.len());
+    assert_eq!(Ok(10), chain.resolve(0, "b"));
+    assert_eq!(Ok(20), chain.resolve(5, "b"));
+    assert_eq!(Ok(21), chain.resolve(6, "b"));
+    assert_eq!(Ok(0), chain.resolve(12, "c"));
+
+    // Merged rules are checked for overlaps even when parsing is not strict.
+    let overlapping = text.replace("20 5 5", "20 3 5");
+    let error = parse_with_options(overlapping.as_bytes(), &merge).unwrap_err();
+    assert_eq!(9, error.line);
+    assert_eq!("line 9: 1 pair(s) of rules with overlapping source ranges, first at 3..5", error.to_string());
+}
+
+#[test]
+fn test_parse_strict() {
+    let text = "seeds: 1 2\n\na-to-b map:\n10 0 5\n\nb-to-c map:\n0 12 3\n20 10 5\n";
+    assert!(parse(text.as_bytes(), false).is_ok());
+
+    let error = parse(text.as_bytes(), true).unwrap_err();
+    assert_eq!(6, error.line);
+    assert_eq!("b-to-c map:", error.text);
+    assert_eq!("line 6: 1 pair(s) of rules with overlapping source ranges, first at 12..15", error.to_string());
+}
+
+#[test]
+fn test_range_map_from_str() {
+    let range_map: RangeMap = "seed-to-soil map:\n50 98 2\n52 50 48".parse().unwrap();
+    assert_eq!(RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]), range_map);
+
+    let range_map: RangeMap = "\n\n  50 98 2\n\n52 50 48  \n\n".parse().unwrap();
+    assert_eq!(RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]), range_map);
+
+    assert_eq!(RangeMap::<u64>::default(), "a-to-b map:\n".parse().unwrap());
+
+    let error = "\na-to-b map:\n50 98 2\n52 50\n".parse::<RangeMap>().unwrap_err();
+    assert_eq!("line 4: expected 3 numbers in a rule of section `a-to-b`, found 2", error.to_string());
+    let error = "50 98 2\n52 50 48 1\n".parse::<RangeMap>().unwrap_err();
+    assert_eq!("line 2: expected 3 numbers, found 4", error.to_string());
+
+    let error = "50 98 2\na-to-b map:\n".parse::<RangeMap>().unwrap_err();
+    assert_eq!("line 2: invalid number `a-to-b`", error.to_string());
+}
+
+#[test]
+fn test_parse_rule_overflow() {
+    let max = u64::MAX;
+    assert!(parse_rule::<u64>(&format!("0 {} 10", max - 10), 1, None).is_ok());
+    assert!(parse_rule::<u64>(&format!("{} 0 10", max - 10), 1, None).is_ok());
+
+    let error = parse_rule::<u64>(&format!("1 {} 10", max), 7, None).unwrap_err();
+    assert_eq!(7, error.line);
+    assert!(matches!(error.kind, ParseErrorKind::RuleOverflow { .. }));
+    let overflows = |rule: String| {
+        matches!(parse_rule::<u64>(&rule, 1, None).unwrap_err().kind, ParseErrorKind::RuleOverflow { .. })
+    };
+    assert!(overflows(format!("{} 0 10", max - 9)));
+    assert!(overflows(format!("0 {} 11", max - 10)));
+
+    let error = parse(format!("seeds: 1\n\na-to-b map:\n0 {} 100\n", max - 5).as_bytes(), false).unwrap_err();
+    assert_eq!(4, error.line);
+    assert!(matches!(error.kind, ParseErrorKind::RuleOverflow { .. }));
+
+    // A rule may end exactly at `u64::MAX`, which is exclusive and so always maps to itself.
+    let (_, chain) = parse(format!("seeds: 1\n\na-to-b map:\n0 {} 10\n", max - 10).as_bytes(), false).unwrap();
+    assert_eq!(Ok(9), chain.resolve(max - 1, "b"));
+    assert_eq!(Ok(max), chain.resolve(max, "b"));
+}
+
+#[test]
+fn test_parse_seed_range_overflow() {
+    let max = u64::MAX;
+    let rules = "\nseed-to-soil map:\n50 98 2\n";
+    assert!(parse(format!("seeds: 1 2 {} 10{}", max - 10, rules).as_bytes(), false).is_ok());
+
+    let error = parse(format!("seeds: 1 2 {} 10 7{}", max - 5, rules).as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::SeedRangeOverflow));
+    assert_eq!(1, error.line);
+    assert_eq!("line 1: seed range overflows, start + size must fit in 64 bits", error.to_string());
+    assert_eq!(&format!("{} 10", max - 5), &error.text[error.span.unwrap()]);
+
+    // A pair split across the lines continuing the seeds is reported where it starts.
+    let error = parse(format!("seeds: 1 2 {}\n10{}", max, rules).as_bytes(), false).unwrap_err();
+    assert_eq!(1, error.line);
+    assert_eq!(&max.to_string(), &error.text[error.span.unwrap()]);
+
+    let json = format!(r#"{{"seeds": [{}, 10], "maps": []}}"#, max);
+    let error = parse_json(json.as_bytes()).unwrap_err();
+    assert!(error.to_string().contains("seed range overflows"), "{}", error);
+}
+
+#[test]
+fn test_parse_invalid_chain() {
+    let error = parse("seeds: 1\n\na-to-b map:\n\nb-to-c map:\n\nc-to-b map:\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 7: section maps back to category `b`, forming a cycle", error.to_string());
+
+    let error = parse("seeds: 1\n\na-to-b map:\n1 2 3\n\nc-to-d map:\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 6: section cannot be reached from category `a`", error.to_string());
+
+    let error = parse("seeds: 1\n\na-to-b map:\n\nb-to-c map:\n\na-to-d map:\n".as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::BranchingSection { first_line: 3, .. }));
+    assert_eq!("line 7: category `a` is already mapped by the section on line 3", error.to_string());
+
+    // Without a category that no section maps to, the walk starts at the first section.
+    let error = parse("seeds: 1\n\nb-to-a map:\n\na-to-b map:\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 5: section maps back to category `b`, forming a cycle", error.to_string());
+}
+
+#[test]
+fn test_parse_section_order() {
+    let text = "seeds: 1 7\n\nb-to-c map:\n0 12 3\n\nseed-to-a map:\n\nc-to-d map:\n\na-to-b map:\n10 0 5\n";
+    let (_, chain) = parse(text.as_bytes(), false).unwrap();
+    let labels: Vec<&str> = chain.range_maps().iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(vec!["a", "b", "c", "d"], labels);
+    assert_eq!(Some("seed"), chain.source(0));
+    assert_eq!(Ok(11), chain.resolve(1, "d"));
+    assert_eq!(Ok(0), chain.resolve(12, "d"));
+
+    // `seed` is preferred as the start, wherever its section is.
+    let error = parse("seeds: 1\n\nx-to-y map:\n\nseed-to-soil map:\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 3: section cannot be reached from category `seed`", error.to_string());
+}
+
+#[test]
+fn test_parse_comments_and_whitespace() {
+    let text = "# my notes\n  seeds: 1 7  \n   \n# first map\n  a-to-b   map:  \n\t10 0 5\n# 1 2 3\n\nb-to-c map:\n  0 12 3\n";
+    let (seeds, chain) = parse(text.as_bytes(), false).unwrap();
+
+    assert_eq!(vec![1, 7], seeds);
+    assert_eq!(Some("a"), chain.source(0));
+    assert_eq!(1, chain.range_maps()[0].1.len());
+    assert_eq!(Ok(11), chain.resolve(1, "b"));
+
+    let range_map: RangeMap = "# rules\n a-to-b  map:\n# comment\n50 98 2\n".parse().unwrap();
+    assert_eq!(RangeMap::new(vec![(50, 98, 2)]), range_map);
+    assert_eq!(None, section_label("a-to-bmap:"));
+}
+
+#[test]
+fn test_parse_unknown_header() {
+    let error = parse("seeds: 1\n\nseed-to-soil mpa:\n1 2 3\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 3: expected a `<from>-to-<to> map:` section header", error.to_string());
+    assert_eq!("seed-to-soil mpa:", error.text);
+
+    let error = parse("seeds: 1\n\nsed to soil map:\n1 2 3\n".as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::UnknownHeader));
+
+    let error = parse("seeds: 1\n\n1 2 3\na-to-b map:\n".as_bytes(), false).unwrap_err();
+    assert_eq!("line 3: rule before any `<from>-to-<to> map:` section", error.to_string());
+
+    // A typo that still looks like a header is caught by the sections not forming a path.
+    let text = "seeds: 1\na-to-b map:\n1 2 3\nb-to-cc map:\n4 5 6\nc-to-d map:\n7 8 9\n";
+    let error = parse(text.as_bytes(), false).unwrap_err();
+    assert_eq!("line 6: section cannot be reached from category `a`", error.to_string());
+}
+
+#[test]
+fn test_parse_multiline_seeds() {
+    let rules = "\na-to-b map:\n10 0 5\n";
+    let single = parse(format!("seeds: 1 7 3 2{}", rules).as_bytes(), false).unwrap();
+
+    assert_eq!(single, parse(format!("seeds: 1 7\n3 2\n{}", rules).as_bytes(), false).unwrap());
+    assert_eq!(single, parse(format!("seeds: 1\n7 3\n  2\n{}", rules).as_bytes(), false).unwrap());
+    assert_eq!(single, parse(format!("seeds:\n\n1 7\n# more\n3 2\n{}", rules).as_bytes(), false).unwrap());
+    assert_eq!(single, parse("seeds: 1 7\n3 2\na-to-b map:\n10 0 5\n".as_bytes(), false).unwrap());
+
+    let error = parse("seeds: 1 7\n\n3 2 1\na-to-b map:\n".as_bytes(), false).unwrap_err();
+    assert!(matches!(error.kind, ParseErrorKind::RuleOutsideSection));
+}
+
+#[test]
+fn test_parse_crlf_and_bom() {
+    let unix = parse("seeds: 1 7\n\na-to-b map:\n10 0 5\n".as_bytes(), false).unwrap();
+
+    assert_eq!(unix, parse("\u{feff}seeds: 1 7\r\n\r\na-to-b map:\r\n10 0 5\r\n".as_bytes(), false).unwrap());
+    assert_eq!(unix, parse("\u{feff}seeds: 1 7\n\na-to-b map:\n10 0 5\n".as_bytes(), false).unwrap());
+}
+
+#[test]
+fn test_parse_json() {
+    let json = r#"{"seeds": [1, 7], "maps": [{"from": "a", "to": "b", "rules": [[10, 0, 5]]}]}"#;
+    assert_eq!(parse("seeds: 1 7\na-to-b map:\n10 0 5\n".as_bytes(), false).unwrap(), parse_json(json.as_bytes()).unwrap());
+
+    let json = "{\"seeds\": [1],\n\"maps\": [{\"from\": \"a\", \"to\": \"b\", \"rules\": [[1, 2]]}]}";
+    let error = parse_json(json.as_bytes()).unwrap_err();
+    assert_eq!(2, error.line);
+    assert!(matches!(error.kind, ParseErrorKind::Json(_)));
+
+    let json = r#"{"seeds": [1], "maps": [{"from": "a", "to": "b", "rules": []}, {"from": "c", "to": "d", "rules": []}]}"#;
+    assert!(parse_json(json.as_bytes()).unwrap_err().to_string().contains("stage 2 resolves from 'c'"));
+}
+
+#[test]
+fn test_parse_bytes_matches_reader() {
+    let describe = |parsed: Result<(Vec<u64>, RangeMapChain), ParseError>| {
+        parsed.map_err(|e| (e.line, e.text.clone(), e.span.clone(), e.to_string()))
+    };
+    let mut inputs: Vec<Vec<u8>> = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data"))
+        .unwrap()
+        .map(|entry| std::fs::read(entry.unwrap().path()).unwrap())
+        .collect();
+    inputs.extend(
+        [
+            "",
+            "\n",
+            "\n\n# only a comment",
+            "\u{feff}seeds: 1 7\r\n\r\na-to-b map:\r\n10 0 5\r\n",
+            "seeds: 1 7\n\na-to-b map:\n10 0 5",
+            "seeds: 1 7\n\na-to-b map:\n10 0 5\n\n\n",
+            "seeds: 1 x\n",
+            "seeds:\n1\n2\n\na-to-b map:\n10 0 5 6\n",
+            "seeds: 1\n\na-to-b map:\n10 0 5\nb-to-c map:\n1 2\n",
+        ]
+        .map(|text| text.as_bytes().to_vec()),
+    );
+    inputs.push(b"seeds: 1 2\n\na-to-b map:\n10 \xff 5\n".to_vec());
+
+    for options in [ParseOptions::default(), ParseOptions { strict: true, merge_duplicate_sections: true }] {
+        for input in &inputs {
+            assert_eq!(
+                describe(parse_with_options(&input[..], &options)),
+                describe(parse_bytes(input, &options)),
+                "{}",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+    let error = parse_bytes(b"seeds: 1 2\n\na-to-b map:\n10 \xff 5\n", &ParseOptions::default()).unwrap_err();
+    assert_eq!(4, error.line);
+    assert!(matches!(error.kind, ParseErrorKind::Io(_)));
+}
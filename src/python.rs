@@ -0,0 +1,48 @@
+//! Python bindings, for building the solver as an extension module with `maturin`.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::Almanac;
+
+/// A parsed almanac, as `Almanac` on the Rust side.
+#[pyclass(name = "Almanac", frozen)]
+struct PyAlmanac(Almanac);
+
+#[pymethods]
+impl PyAlmanac {
+    /// Parses the full text of an almanac, raising `ValueError` with the parse error message.
+    #[staticmethod]
+    fn parse(text: &str) -> PyResult<Self> {
+        Almanac::parse(text).map(PyAlmanac).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Lowest location of the seeds for part 1 or 2 of the puzzle, or `None` without seeds.
+    fn min_location(&self, part: u8) -> PyResult<Option<u64>> {
+        match part {
+            1 => Ok(self.0.lowest_location()),
+            2 => self.0.lowest_location_for_ranges().map_err(|e| PyValueError::new_err(e.to_string())),
+            _ => Err(PyValueError::new_err(format!("invalid part `{}`, expected 1 or 2", part))),
+        }
+    }
+
+    /// Resolves `seed` through the whole chain.
+    fn resolve(&self, seed: u64) -> u64 {
+        self.0.chain.resolve_at(seed, self.0.chain.len())
+    }
+
+    /// The `(category, value)` pairs `seed` goes through, one per map of the chain.
+    fn trace(&self, seed: u64) -> Vec<(String, u64)> {
+        self.0.chain.resolve_trace(seed)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Almanac(seeds={}, maps={})", self.0.seeds.len(), self.0.chain.len())
+    }
+}
+
+#[pymodule]
+fn aoc2023_day05(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyAlmanac>()?;
+    Ok(())
+}
@@ -0,0 +1,1264 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Add, AddAssign, Sub};
+use std::str::FromStr;
+
+use crate::chain::intersect_ranges;
+use crate::merge_ranges;
+
+/// Two rules of a `RangeMap` whose source intervals overlap. `first` and `second` index into
+/// `RangeMap::ranges`, and `start..start + size` is the overlapping part of the source intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overlap<T = u64> {
+    pub first: usize,
+    pub second: usize,
+    pub start: T,
+    pub size: T,
+}
+
+/// Which map's rules win where the source intervals of two maps overlap, in `RangeMap::union`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precedence {
+    /// The rules of the map `union` is called on win.
+    Left,
+    /// The rules of the map passed to `union` win.
+    Right,
+}
+
+/// An unsigned integer type the values of a `RangeMap` can have, `u64` by default. Arithmetic on
+/// values is checked or saturating wherever it could overflow, so every type behaves the same near
+/// its own `MAX`.
+pub trait MapValue:
+    Copy + Ord + Default + Hash + fmt::Debug + fmt::Display + FromStr
+    + Add<Output = Self> + Sub<Output = Self> + AddAssign
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    const BITS: u32;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    fn saturating_add(self, other: Self) -> Self;
+}
+
+macro_rules! impl_map_value {
+    ($($t:ty),*) => {
+        $(
+            impl MapValue for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+                const MAX: Self = <$t>::MAX;
+                const BITS: u32 = <$t>::BITS;
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn saturating_add(self, other: Self) -> Self {
+                    <$t>::saturating_add(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_map_value!(u32, u64, u128, usize);
+
+/// A set of rules mapping source intervals to destination intervals, as found in
+/// a single section of the almanac. Each rule is a `(destination, source, size)`
+/// triple. Rules are kept sorted by source start so lookups can binary search.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RangeMap<T = u64> {
+    ranges: Vec<(T, T, T)>,
+    /// `max_ends[i]` is the greatest source end among the rules up to `ranges[i]`. It never
+    /// decreases, so the rules that can intersect an interval are found by binary search even
+    /// when rules overlap.
+    max_ends: Vec<T>,
+}
+
+impl RangeMap {
+
+    /// Builds a map from a list of `(destination, source, size)` rules.
+    pub fn new(ranges: Vec<(u64, u64, u64)>) -> Self {
+        Self::from_rules(ranges)
+    }
+}
+
+impl<T: MapValue> RangeMap<T> {
+
+    /// Like `new`, for rules of any value type: `RangeMap::<u128>::from_rules(rules)`.
+    pub fn from_rules(mut ranges: Vec<(T, T, T)>) -> Self {
+        ranges.sort_by_key(|&(_, source, _)| source);
+        let mut range_map = Self { ranges, max_ends: Vec::new() };
+        range_map.update_max_ends(0);
+        range_map
+    }
+
+    /// Inserts a single `(destination, source, size)` rule, keeping the rules sorted.
+    pub fn push(&mut self, range: (T, T, T)) {
+        let index = self.ranges.partition_point(|&(_, source, _)| source <= range.1);
+        self.ranges.insert(index, range);
+        self.update_max_ends(index);
+    }
+
+    /// Recomputes `max_ends` from the rule at `from` onwards.
+    fn update_max_ends(&mut self, from: usize) {
+        self.max_ends.truncate(from);
+        let mut max_end = self.max_ends.last().copied().unwrap_or(T::ZERO);
+        for &(_, source, size) in &self.ranges[from..] {
+            max_end = max_end.max(source.saturating_add(size));
+            self.max_ends.push(max_end);
+        }
+    }
+
+    /// The rules that may intersect the source interval `start..end`, skipping those that end
+    /// before `start` or begin at or after `end`.
+    fn window(&self, start: T, end: T) -> &[(T, T, T)] {
+        let first = self.max_ends.partition_point(|&max_end| max_end <= start);
+        let last = self.ranges.partition_point(|&(_, source, _)| source < end);
+        &self.ranges[first..last.max(first)]
+    }
+
+    /// Returns the rules of this map sorted by source start.
+    pub fn ranges(&self) -> &[(T, T, T)] {
+        &self.ranges
+    }
+
+    /// Iterates over the `(destination, source, size)` rules sorted by source start.
+    pub fn iter(&self) -> std::slice::Iter<'_, (T, T, T)> {
+        self.ranges.iter()
+    }
+
+    /// Number of rules in the map.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Merges rules whose source intervals are contiguous and that share the same destination
+    /// offset, so `(52, 50, 10)` and `(62, 60, 5)` become `(52, 50, 15)`. Lookups are unchanged.
+    pub fn normalize(&mut self) {
+        let mut merged: Vec<(T, T, T)> = Vec::with_capacity(self.ranges.len());
+        for &(destination, source, size) in self.ranges.iter() {
+            match merged.last_mut() {
+                Some((last_destination, last_source, last_size))
+                    if last_source.checked_add(*last_size) == Some(source)
+                        && last_destination.checked_add(*last_size) == Some(destination)
+                        && last_size.checked_add(size).is_some() =>
+                {
+                    *last_size += size;
+                }
+                _ => merged.push((destination, source, size)),
+            }
+        }
+        self.ranges = merged;
+        self.update_max_ends(0);
+    }
+
+    /// Checks that no two rules have overlapping source intervals, returning every overlapping
+    /// pair otherwise. Lookups through `get` and `get_ranges` may disagree on overlapping rules.
+    pub fn validate(&self) -> Result<(), Vec<Overlap<T>>> {
+        let mut overlaps = Vec::new();
+        for (first, &(_, source, size)) in self.ranges.iter().enumerate() {
+            for (offset, &(_, other_source, other_size)) in self.ranges[first + 1..].iter().enumerate() {
+                if other_source >= source.saturating_add(size) {
+                    break;
+                }
+                let end = source.saturating_add(size).min(other_source.saturating_add(other_size));
+                if other_source < end {
+                    overlaps.push(Overlap { first, second: first + 1 + offset, start: other_source, size: end - other_source });
+                }
+            }
+        }
+        if overlaps.is_empty() {
+            Ok(())
+        } else {
+            Err(overlaps)
+        }
+    }
+
+    /// Whether any rule's source interval covers `value`. Unlike `get(value).is_some()`, this holds
+    /// for every rule when rules overlap.
+    pub fn contains(&self, value: T) -> bool {
+        self.window(value, value.saturating_add(T::ONE))
+            .iter()
+            .any(|&(_, source, size)| source <= value && value - source < size)
+    }
+
+    /// Number of source values covered by at least one rule, counting overlaps once.
+    pub fn covered_size(&self) -> T {
+        let uncovered = self.gaps((T::ZERO, T::MAX)).iter().fold(T::ZERO, |sum, &(_, size)| sum + size);
+        T::MAX - uncovered
+    }
+
+    /// Lowest source value covered by a rule, or `None` for a map without rules.
+    pub fn min_source(&self) -> Option<T> {
+        self.ranges.iter().find(|&&(_, _, size)| size > T::ZERO).map(|&(_, source, _)| source)
+    }
+
+    /// Highest source value covered by a rule, or `None` for a map without rules.
+    pub fn max_source(&self) -> Option<T> {
+        self.ranges
+            .iter()
+            .filter(|&&(_, _, size)| size > T::ZERO)
+            .map(|&(_, source, size)| source.saturating_add(size - T::ONE))
+            .max()
+    }
+
+    /// Returns every pair of rules whose destination intervals intersect, as `(first, second,
+    /// (start, size))` with `first < second` indexing into `ranges` and `start..start + size` the
+    /// shared part of the destinations. Such a map sends different sources to the same values, so
+    /// `invert` cannot undo it.
+    pub fn destination_overlaps(&self) -> Vec<(usize, usize, (T, T))> {
+        let mut by_destination: Vec<usize> = (0..self.ranges.len()).collect();
+        by_destination.sort_by_key(|&index| self.ranges[index].0);
+        let mut overlaps = Vec::new();
+        for (position, &first) in by_destination.iter().enumerate() {
+            let (destination, _, size) = self.ranges[first];
+            let end = destination.saturating_add(size);
+            for &second in &by_destination[position + 1..] {
+                let (other_destination, _, other_size) = self.ranges[second];
+                if other_destination >= end {
+                    break;
+                }
+                let overlap_end = end.min(other_destination.saturating_add(other_size));
+                if other_destination < overlap_end {
+                    let region = (other_destination, overlap_end - other_destination);
+                    overlaps.push((first.min(second), first.max(second), region));
+                }
+            }
+        }
+        overlaps.sort_unstable();
+        overlaps
+    }
+
+    /// Whether no two rules map onto overlapping destination intervals. Values that no rule
+    /// covers still map to themselves and may collide with a rule's destination.
+    pub fn is_injective(&self) -> bool {
+        self.destination_overlaps().is_empty()
+    }
+
+    /// Returns the destination for `value`, or `None` if no rule covers it. When rules overlap, the
+    /// rule covering `value` with the greatest source start wins, even if an earlier, longer rule
+    /// covers it too. A destination that would overflow `T` is reported as `None` rather than
+    /// wrapping.
+    pub fn get(&self, value: T) -> Option<T> {
+        self.get_with_rule(value).map(|(mapped, _)| mapped)
+    }
+
+    /// Like `get`, but also returns the index of the matching rule in `iter` order, as a
+    /// `(mapped, rule_index)` pair.
+    pub fn get_with_rule(&self, value: T) -> Option<(T, usize)> {
+        // As in `window`, rules ending at or before `value` are skipped through `max_ends`.
+        let first = self.max_ends.partition_point(|&max_end| max_end <= value);
+        let last = self.ranges.partition_point(|&(_, source, _)| source <= value);
+        let index = (first..last).rev().find(|&index| self.covers(index, value))?;
+        let (destination, source, _) = self.ranges[index];
+        Some((destination.checked_add(value - source)?, index))
+    }
+
+    /// Whether the rule at `index`, whose source start is at most `value`, covers `value`.
+    fn covers(&self, index: usize, value: T) -> bool {
+        let (_, source, size) = self.ranges[index];
+        value - source < size
+    }
+
+    /// Looks up every one of `values`, returning the same as calling `get` on each, in the same
+    /// order. The values are sorted and swept against the rules in a single pass instead of binary
+    /// searching the rules for each of them, which pays off for maps with many rules or values that
+    /// are mostly sorted already. For a few thousand rules and scattered values, sorting costs more
+    /// than the binary searches it saves.
+    pub fn get_many(&self, values: &[T]) -> Vec<Option<T>> {
+        let mut queries: Vec<(T, usize)> = values.iter().copied().zip(0..).collect();
+        queries.sort_unstable();
+        let mut mapped = vec![None; values.len()];
+        // Number of rules starting at or before the current value, and of those ending before it.
+        let mut next = 0;
+        let mut first = 0;
+        for (value, index) in queries {
+            while next < self.ranges.len() && self.ranges[next].1 <= value {
+                next += 1;
+            }
+            while first < next && self.max_ends[first] <= value {
+                first += 1;
+            }
+            if let Some(rule) = (first..next).rev().find(|&rule| self.covers(rule, value)) {
+                let (destination, source, _) = self.ranges[rule];
+                mapped[index] = destination.checked_add(value - source);
+            }
+        }
+        mapped
+    }
+
+    /// Like `get`, but values not covered by any rule map to themselves.
+    pub fn get_or_identity(&self, value: T) -> T {
+        self.get(value).unwrap_or(value)
+    }
+
+    /// Returns the map obtained by swapping destination and source in every rule, so
+    /// `invert().get(y)` answers which source value maps to `y`. If two rules map onto
+    /// overlapping destination ranges the inverted rules overlap too, and lookups follow the
+    /// overlap policy of `get`.
+    pub fn invert(&self) -> RangeMap<T> {
+        RangeMap::from_rules(
+            self.ranges
+                .iter()
+                .map(|&(destination, source, size)| (source, destination, size))
+                .collect()
+        )
+    }
+
+    /// Returns every source value that maps to `value`: one per rule whose destination range
+    /// covers it, plus `value` itself if no rule covers it as a source. Sorted and deduplicated.
+    pub fn preimages(&self, value: T) -> Vec<T> {
+        let mut preimages: Vec<T> = self.ranges
+            .iter()
+            .filter(|&&(destination, _, size)| destination <= value && value - destination < size)
+            .filter_map(|&(destination, source, _)| source.checked_add(value - destination))
+            .collect();
+        if self.get(value).is_none() {
+            preimages.push(value);
+        }
+        preimages.sort();
+        preimages.dedup();
+        preimages
+    }
+
+    /// Returns the source intervals that map into any of the `(start, size)` ranges: the
+    /// destination side of every rule is clipped to the ranges and translated back, and the parts
+    /// of the ranges no rule covers as a source are their own preimage. The result is unsorted and
+    /// may overlap.
+    pub fn preimage_ranges(&self, ranges: &[(T, T)]) -> Vec<(T, T)> {
+        let inverted = self.invert();
+        ranges
+            .iter()
+            .flat_map(|&(start, size)| {
+                inverted
+                    .clip(start, start.saturating_add(size))
+                    .map(|(source, _, size)| (source, size))
+                    .chain(self.gaps((start, size)))
+                    .collect::<Vec<(T, T)>>()
+            })
+            .collect()
+    }
+
+    /// Returns the maximal intervals of the `(start, size)` domain that no rule covers, as
+    /// `(start, size)` pairs sorted by start.
+    pub fn gaps(&self, domain: (T, T)) -> Vec<(T, T)> {
+        let (start, size) = domain;
+        let end = start.saturating_add(size);
+        let mut gaps = Vec::new();
+        let mut cursor = start;
+        for &(_, source, s_size) in self.ranges.iter() {
+            if source >= end {
+                break;
+            }
+            if source > cursor {
+                gaps.push((cursor, source - cursor));
+            }
+            cursor = cursor.max(source.saturating_add(s_size));
+        }
+        if cursor < end {
+            gaps.push((cursor, end - cursor));
+        }
+        gaps
+    }
+
+    /// Returns the map with an identity rule added for every gap in `0..domain_end`, so `get` never
+    /// returns `None` below `domain_end`. Rules reaching past `domain_end` are kept as they are.
+    pub fn to_total(&self, domain_end: T) -> RangeMap<T> {
+        let identity = self.gaps((T::ZERO, domain_end)).into_iter().map(|(start, size)| (start, start, size));
+        RangeMap::from_rules(self.ranges.iter().copied().chain(identity).collect())
+    }
+
+    /// Returns the map without its identity rules, the ones mapping their source interval onto
+    /// itself. Lookups through `get_or_identity` are unchanged.
+    pub fn without_identity(&self) -> RangeMap<T> {
+        let ranges = self.ranges.iter().copied().filter(|&(destination, source, _)| destination != source);
+        RangeMap::from_rules(ranges.collect())
+    }
+
+    /// Returns a single map equivalent to applying `self` and then `next`, so that
+    /// `composed.get_or_identity(x) == next.get_or_identity(self.get_or_identity(x))`.
+    /// Rules are split wherever the intervals of the two maps intersect. Assumes neither map has
+    /// overlapping source ranges.
+    pub fn compose(&self, next: &RangeMap<T>) -> RangeMap<T> {
+        let mut ranges = Vec::new();
+        for &(destination, source, size) in self.ranges.iter() {
+            let size = representable_size(destination, source, size);
+            let mut offset = T::ZERO;
+            for (output, output_size) in next.partition(destination, size) {
+                ranges.push((output, source + offset, output_size));
+                offset += output_size;
+            }
+        }
+        for (start, size) in self.gaps((T::ZERO, T::MAX)) {
+            ranges.extend(next.clip(start, start + size));
+        }
+        RangeMap::from_rules(ranges)
+    }
+
+    /// Returns a map with the rules of both maps, overlaying one on the other: where source
+    /// intervals overlap, the map given by `precedence` wins and the rules of the other are clipped
+    /// to its gaps. The result has no overlapping source ranges as long as neither map has any.
+    pub fn union(&self, other: &RangeMap<T>, precedence: Precedence) -> RangeMap<T> {
+        let (winner, loser) = match precedence {
+            Precedence::Left => (self, other),
+            Precedence::Right => (other, self),
+        };
+        let clipped = loser.ranges.iter().flat_map(|&(destination, source, size)| {
+            winner
+                .gaps((source, size))
+                .into_iter()
+                .filter_map(move |(start, size)| Some((destination.checked_add(start - source)?, start, size)))
+        });
+        RangeMap::from_rules(winner.ranges.iter().copied().chain(clipped).collect())
+    }
+
+    /// Returns the source intervals covered by a rule of both maps, as sorted, disjoint
+    /// `(start, size)` pairs.
+    pub fn intersect_domains(&self, other: &RangeMap<T>) -> Vec<(T, T)> {
+        let domain = |range_map: &RangeMap<T>| {
+            merge_ranges(range_map.ranges.iter().map(|&(_, source, size)| (source, size)).collect())
+        };
+        intersect_ranges(&domain(self), &domain(other))
+    }
+
+    /// Returns the parts of `intersect_domains` where the two maps send values to different
+    /// destinations, as sorted, disjoint `(start, size)` pairs. Found by comparing the two maps
+    /// on every piece of `pieces`, through its first value only.
+    pub fn differs_on(&self, other: &RangeMap<T>) -> Vec<(T, T)> {
+        let differing = self
+            .intersect_domains(other)
+            .into_iter()
+            .flat_map(|domain| self.pieces(other, domain))
+            .filter(|&(start, _)| self.get(start) != other.get(start))
+            .collect();
+        merge_ranges(differing)
+    }
+
+    /// Whether both maps send every value of the `(start, size)` domain to the same place, with
+    /// values outside every rule mapping to themselves. Compares the maps on every piece of
+    /// `pieces`, so a difference at a single value is found without enumerating the domain.
+    pub fn equivalent(&self, other: &RangeMap<T>, domain: (T, T)) -> bool {
+        self.pieces(other, domain)
+            .into_iter()
+            .all(|(start, _)| self.get_or_identity(start) == other.get_or_identity(start))
+    }
+
+    /// Splits the `(start, size)` domain at every rule boundary of either map, so that on each
+    /// `(start, size)` piece both maps translate every value by a fixed offset, or leave it be.
+    fn pieces(&self, other: &RangeMap<T>, domain: (T, T)) -> Vec<(T, T)> {
+        let (start, size) = domain;
+        let end = start.saturating_add(size);
+        let mut boundaries: Vec<T> = self
+            .window(start, end)
+            .iter()
+            .chain(other.window(start, end))
+            .flat_map(|&(_, source, size)| [source, source.saturating_add(size)])
+            .filter(|&boundary| start < boundary && boundary < end)
+            .chain([start, end])
+            .collect();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        boundaries.windows(2).map(|pair| (pair[0], pair[1] - pair[0])).collect()
+    }
+
+    /// Returns the map with every rule clipped to the `(start, size)` domain intervals, so lookups
+    /// inside the domain are unchanged and every value outside it is uncovered. Rules spanning a
+    /// domain boundary are split and rules outside the domain are dropped. Assumes the map has no
+    /// overlapping source ranges.
+    pub fn restrict(&self, domain: &[(T, T)]) -> RangeMap<T> {
+        RangeMap::from_rules(
+            merge_ranges(domain.into())
+                .into_iter()
+                .flat_map(|(start, size)| self.clip(start, start.saturating_add(size)))
+                .collect()
+        )
+    }
+
+    /// Returns the rules of this map restricted to the source interval `start..end`.
+    fn clip(&self, start: T, end: T) -> impl Iterator<Item = (T, T, T)> + '_ {
+        self.window(start, end).iter().filter_map(move |&(destination, source, size)| {
+            let size = representable_size(destination, source, size);
+            let clipped_start = start.max(source);
+            let clipped_end = end.min(source + size);
+            if clipped_start < clipped_end {
+                Some((destination + clipped_start - source, clipped_start, clipped_end - clipped_start))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// This function returns a vector of new ranges from the original range passed in as a parameter.
+    /// The input range is partitioned completely: portions covered by a rule are translated and
+    /// portions outside every rule are passed through unchanged. A range running past `T::MAX`
+    /// is cut short there. The output ranges are sorted by start, so two maps with the same rules
+    /// give the same output whatever order the rules were added in.
+    pub fn get_ranges(&self, start: T, size: T) -> Vec<(T, T)> {
+        let mut output = Vec::new();
+        self.get_ranges_into(start, size, &mut output);
+        output
+    }
+
+    /// Like `get_ranges`, but appends the output ranges to `out` instead of returning a new vector,
+    /// so a caller mapping many ranges can keep reusing one buffer. The appended ranges are sorted
+    /// among themselves; whatever `out` already held is left untouched.
+    pub fn get_ranges_into(&self, start: T, size: T, out: &mut Vec<(T, T)>) {
+        let appended = out.len();
+        self.partition_into(start, size, out);
+        out[appended..].sort_unstable();
+    }
+
+    /// Like `get_ranges`, but yields the output ranges one at a time, sorted by start. Nothing is
+    /// mapped until the first range is read; then the portions of the query are heaped in one
+    /// linear pass over the rules that may intersect it, and each further range costs a pop, so
+    /// a caller after the lowest output range never pays for sorting the rest.
+    pub fn map_ranges(&self, start: T, size: T) -> impl Iterator<Item = (T, T)> + '_ {
+        MapRanges { portions: Some(self.portions(start, size)), heap: BinaryHeap::new() }
+    }
+
+    /// Walks the `start..start + size` query in source order, yielding the output range of each
+    /// portion a rule covers and passing the portions between rules through unchanged.
+    fn portions(&self, start: T, size: T) -> Portions<'_, T> {
+        let end = start.saturating_add(size);
+        Portions { rules: self.window(start, end).iter(), cursor: start, end, pending: None }
+    }
+
+    /// Splits the `start..start + size` query into the output ranges of the portions some rule
+    /// covers, sorted by start, and the portions no rule covers, left in source coordinates as
+    /// `gaps` returns them. Assumes the map has no overlapping source ranges.
+    #[allow(clippy::type_complexity)]
+    pub fn get_ranges_partition(&self, start: T, size: T) -> (Vec<(T, T)>, Vec<(T, T)>) {
+        let mut mapped: Vec<(T, T)> = self
+            .clip(start, start.saturating_add(size))
+            .map(|(destination, _, size)| (destination, size))
+            .collect();
+        mapped.sort_unstable();
+        (mapped, self.gaps((start, size)))
+    }
+
+    /// Like `get_ranges`, but the output ranges follow the order of the input portions they come
+    /// from, so their sizes add up to offsets into `start..start + size`.
+    pub(crate) fn partition(&self, start: T, size: T) -> Vec<(T, T)> {
+        let mut output = Vec::new();
+        self.partition_into(start, size, &mut output);
+        output
+    }
+
+    /// Like `partition`, appending the output ranges to `out`.
+    pub(crate) fn partition_into(&self, start: T, size: T, out: &mut Vec<(T, T)>) {
+        out.extend(self.portions(start, size));
+    }
+
+}
+
+/// Size of the part of the `(destination, source, size)` rule whose source and destination
+/// intervals both end within `T`. Range lookups leave the values past it to map to themselves,
+/// as `get_or_identity` does for a value whose destination would overflow, since no range of
+/// `T` can hold their destinations.
+fn representable_size<T: MapValue>(destination: T, source: T, size: T) -> T {
+    size.min(T::MAX - destination).min(T::MAX - source)
+}
+
+/// The iterator returned by `RangeMap::map_ranges`.
+struct MapRanges<'a, T> {
+    /// The source-order walk of the query, until the first read moves it into `heap`.
+    portions: Option<Portions<'a, T>>,
+    heap: BinaryHeap<Reverse<(T, T)>>,
+}
+
+impl<T: MapValue> Iterator for MapRanges<'_, T> {
+    type Item = (T, T);
+
+    fn next(&mut self) -> Option<(T, T)> {
+        if let Some(portions) = self.portions.take() {
+            self.heap = portions.map(Reverse).collect();
+        }
+        self.heap.pop().map(|Reverse(range)| range)
+    }
+}
+
+/// The source-order walk behind `RangeMap::partition_into` and `RangeMap::map_ranges`.
+struct Portions<'a, T> {
+    /// The rules left that may intersect the query, sorted by source start.
+    rules: std::slice::Iter<'a, (T, T, T)>,
+    /// Start of the part of the query not mapped yet.
+    cursor: T,
+    end: T,
+    /// The portion a rule maps, found with the gap before it and yielded right after the gap.
+    pending: Option<(T, T)>,
+}
+
+impl<T: MapValue> Iterator for Portions<'_, T> {
+    type Item = (T, T);
+
+    fn next(&mut self) -> Option<(T, T)> {
+        if let Some(range) = self.pending.take() {
+            return Some(range);
+        }
+        for &(destination, source, size) in self.rules.by_ref() {
+            let size = representable_size(destination, source, size);
+            let portion_start = self.cursor.max(source);
+            let portion_end = self.end.min(source + size);
+            if portion_start >= portion_end {
+                continue;
+            }
+            let gap = (self.cursor < portion_start).then(|| (self.cursor, portion_start - self.cursor));
+            let mapped = (destination + portion_start - source, portion_end - portion_start);
+            self.cursor = portion_end;
+            return match gap {
+                Some(gap) => {
+                    self.pending = Some(mapped);
+                    Some(gap)
+                }
+                None => Some(mapped),
+            };
+        }
+        if self.cursor < self.end {
+            let gap = (self.cursor, self.end - self.cursor);
+            self.cursor = self.end;
+            return Some(gap);
+        }
+        None
+    }
+}
+
+
+/// Collects `(destination, source, size)` rules, sorting them like `RangeMap::new`.
+impl<T: MapValue> FromIterator<(T, T, T)> for RangeMap<T> {
+    fn from_iter<I: IntoIterator<Item = (T, T, T)>>(iter: I) -> Self {
+        RangeMap::from_rules(iter.into_iter().collect())
+    }
+}
+
+impl<'a, T: MapValue> IntoIterator for &'a RangeMap<T> {
+    type Item = &'a (T, T, T);
+    type IntoIter = std::slice::Iter<'a, (T, T, T)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Widens rules given as `usize`, which never loses values on targets up to 64 bits.
+impl From<Vec<(usize, usize, usize)>> for RangeMap {
+    fn from(ranges: Vec<(usize, usize, usize)>) -> Self {
+        ranges
+            .into_iter()
+            .map(|(destination, source, size)| (destination as u64, source as u64, size as u64))
+            .collect()
+    }
+}
+
+/// Writes one `destination source size` line per rule, the format `FromStr` reads back.
+impl<T: MapValue> fmt::Display for RangeMap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (destination, source, size) in self.ranges.iter() {
+            writeln!(f, "{} {} {}", destination, source, size)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_resolve_range() {
+    let range_map = RangeMap::new(vec![(100, 0, 50), (200, 50, 50), (500, 100, 100)]);
+
+    assert_eq!(vec![(125, 25), (200, 25)], range_map.get_ranges(25, 50));
+    assert_eq!(vec![(110, 10)], range_map.get_ranges(10, 10));
+
+    // Sorted by start whatever order the rules came in, even when a later portion maps lower.
+    let mut pushed = RangeMap::<u64>::default();
+    for rule in [(500, 100, 100), (100, 50, 50), (200, 0, 50)] {
+        pushed.push(rule);
+    }
+    assert_eq!(vec![(100, 50), (200, 50), (500, 10)], pushed.get_ranges(0, 110));
+    assert_eq!(vec![(200, 50), (100, 50), (500, 10)], pushed.partition(0, 110));
+}
+
+#[test]
+fn test_resolve_range_passes_through_gaps() {
+    let range_map = RangeMap::new(vec![(1000, 100, 100), (2000, 300, 50)]);
+
+    // starts before the first rule
+    assert_eq!(vec![(50, 50), (1000, 20)], range_map.get_ranges(50, 70));
+    // ends after the last rule
+    assert_eq!(vec![(350, 50), (2040, 10)], range_map.get_ranges(340, 60));
+    // spans the gap between two rules
+    assert_eq!(
+        vec![(0, 100), (200, 100), (350, 650), (1000, 100), (2000, 50)],
+        range_map.get_ranges(0, 1000)
+    );
+    assert_eq!(
+        vec![(0, 100), (1000, 100), (200, 100), (2000, 50), (350, 650)],
+        range_map.partition(0, 1000)
+    );
+    // not covered at all
+    assert_eq!(vec![(500, 10)], range_map.get_ranges(500, 10));
+}
+
+#[test]
+fn test_get_matches_linear_scan() {
+    // Rules are generated out of order with holes between them so the sort and the
+    // binary search both get exercised.
+    let rules: Vec<(u64, u64, u64)> = (0..2_000)
+        .map(|i| ((i * 7_919) % 2_000, ((i * 7_919) % 2_000) * 20, 10 + i % 7))
+        .map(|(k, source, size)| (k * 3 + 1_000_000, source, size))
+        .collect();
+    let range_map = RangeMap::new(rules.clone());
+
+    let linear = |value: u64| {
+        rules
+            .iter()
+            .find(|(_, source, size)| *source <= value && value < source + size)
+            .map(|(destination, source, _)| destination + value - source)
+    };
+
+    for value in (0..40_100).step_by(2) {
+        assert_eq!(linear(value), range_map.get(value));
+    }
+
+    // Long rules laid over the others: the covering rule with the greatest source start wins.
+    let long_rules = (0..10).map(|i| (i * 5_000, i * 4_000 + 7, 2_500));
+    let overlapping: Vec<(u64, u64, u64)> = rules.iter().copied().chain(long_rules).collect();
+    let range_map = RangeMap::new(overlapping.clone());
+    let linear = |value: u64| {
+        overlapping
+            .iter()
+            .filter(|(_, source, size)| *source <= value && value < source + size)
+            .max_by_key(|(_, source, _)| *source)
+            .map(|(destination, source, _)| destination + value - source)
+    };
+    for value in 0..40_100 {
+        assert_eq!(linear(value), range_map.get(value), "{}", value);
+    }
+}
+
+#[test]
+fn test_invert() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48), (0, 200, 10)]);
+    let inverted = range_map.invert();
+
+    assert_eq!(vec![(200, 0, 10), (98, 50, 2), (50, 52, 48)], inverted.ranges());
+    for value in (50..100).chain(200..210) {
+        assert_eq!(Some(value), inverted.get(range_map.get(value).unwrap()));
+    }
+    assert_eq!(None, inverted.get(100));
+}
+
+#[test]
+fn test_compose() {
+    let first = RangeMap::new(vec![(50, 98, 2), (52, 50, 48), (1000, 300, 100)]);
+    let second = RangeMap::new(vec![(0, 15, 37), (37, 52, 2), (39, 0, 15), (5000, 1050, 100)]);
+    let composed = first.compose(&second);
+
+    let mut seed: u64 = 12345;
+    let samples = (0..5_000).map(|_| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % 1_500
+    });
+    let boundaries = [first.ranges(), second.ranges()]
+        .into_iter()
+        .flatten()
+        .flat_map(|&(destination, source, size)| [destination, source, source + size]);
+
+    for value in samples.chain(boundaries).flat_map(|v| [v.saturating_sub(1), v, v + 1]) {
+        assert_eq!(
+            second.get_or_identity(first.get_or_identity(value)),
+            composed.get_or_identity(value),
+            "value {}", value
+        );
+    }
+}
+
+#[test]
+fn test_preimages() {
+    let range_map = RangeMap::new(vec![(10, 0, 5), (10, 20, 5), (0, 10, 5)]);
+
+    assert_eq!(vec![2, 22], range_map.preimages(12));
+    assert_eq!(vec![13], range_map.preimages(3));
+    assert_eq!(vec![30], range_map.preimages(30));
+    assert_eq!(Vec::<u64>::new(), range_map.preimages(20));
+}
+
+#[test]
+fn test_validate() {
+    assert_eq!(Ok(()), RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]).validate());
+
+    // get(7) picks the rule starting at 5 while get_ranges(7, 1) follows the one starting at 0.
+    let range_map = RangeMap::new(vec![(100, 0, 10), (200, 5, 10), (300, 8, 1), (400, 20, 5)]);
+    assert_eq!(
+        Err(vec![
+            Overlap { first: 0, second: 1, start: 5, size: 5 },
+            Overlap { first: 0, second: 2, start: 8, size: 1 },
+            Overlap { first: 1, second: 2, start: 8, size: 1 },
+        ]),
+        range_map.validate()
+    );
+}
+
+#[test]
+fn test_gaps() {
+    assert_eq!(vec![(10, 90)], RangeMap::<u64>::default().gaps((10, 90)));
+
+    let range_map = RangeMap::new(vec![(0, 10, 10), (0, 20, 5), (0, 40, 10), (0, 95, 10)]);
+    assert_eq!(vec![(25, 15), (50, 45)], range_map.gaps((10, 90)));
+    assert_eq!(vec![(0, 10), (25, 15), (50, 45), (105, 5)], range_map.gaps((0, 110)));
+    assert_eq!(Vec::<(u64, u64)>::new(), range_map.gaps((12, 13)));
+    assert_eq!(vec![(30, 5)], range_map.gaps((30, 5)));
+}
+
+#[test]
+fn test_normalize() {
+    let mut range_map = RangeMap::new((0..100).rev().map(|i| (i * 3 + 1000, i * 3, 3)).collect());
+    let original = range_map.clone();
+    range_map.normalize();
+
+    assert_eq!(vec![(1000, 0, 300)], range_map.ranges());
+    for value in 0..310 {
+        assert_eq!(original.get(value), range_map.get(value));
+    }
+    assert!(crate::same_ranges(&original.get_ranges(10, 400), &range_map.get_ranges(10, 400)));
+
+    let mut range_map = RangeMap::new(vec![(52, 50, 10), (62, 60, 5), (0, 65, 5), (5, 70, 5)]);
+    range_map.normalize();
+    assert_eq!(vec![(52, 50, 15), (0, 65, 10)], range_map.ranges());
+}
+
+#[test]
+fn test_lookups_near_u64_max() {
+    let range_map = RangeMap::new(vec![(0, u64::MAX - 10, 10), (u64::MAX - 5, 0, 5), (u64::MAX - 1, 100, 5)]);
+
+    assert_eq!(Some(9), range_map.get(u64::MAX - 1));
+    assert_eq!(Some(0), range_map.get(u64::MAX - 10));
+    assert_eq!(None, range_map.get(u64::MAX));
+    assert_eq!(Some(u64::MAX - 1), range_map.get(4));
+    // 102 would land past u64::MAX
+    assert_eq!(Some(u64::MAX), range_map.get(101));
+    assert_eq!(None, range_map.get(102));
+
+    assert_eq!(vec![(0, 10), (u64::MAX - 20, 10)], range_map.get_ranges(u64::MAX - 20, 20));
+    assert_eq!(vec![(0, 10), (u64::MAX - 20, 10)], range_map.get_ranges(u64::MAX - 20, 50));
+    assert!(range_map.gaps((u64::MAX - 10, 10)).is_empty());
+    assert_eq!(vec![(u64::MAX - 20, 10)], range_map.gaps((u64::MAX - 20, 50)));
+}
+
+#[test]
+fn test_collect() {
+    let range_map: RangeMap = [(52, 50, 48), (50, 98, 2)].into_iter().collect();
+
+    assert_eq!(2, range_map.len());
+    assert!(!range_map.is_empty());
+    assert!(RangeMap::<u64>::default().is_empty());
+    assert_eq!(vec![&(52, 50, 48), &(50, 98, 2)], range_map.iter().collect::<Vec<_>>());
+    assert_eq!(Some(51), range_map.get(99));
+    assert_eq!(Some(81), range_map.get(79));
+    assert_eq!(None, range_map.get(100));
+
+    let doubled: RangeMap = range_map.iter().map(|&(destination, source, size)| (destination * 2, source, size)).collect();
+    assert_eq!(Some(101), doubled.get(99));
+}
+
+#[test]
+fn test_get_with_rule() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48), (0, 0, 10)]);
+
+    assert_eq!(Some((0, 0)), range_map.get_with_rule(0));
+    assert_eq!(Some((9, 0)), range_map.get_with_rule(9));
+    assert_eq!(None, range_map.get_with_rule(10));
+    assert_eq!(Some((52, 1)), range_map.get_with_rule(50));
+    assert_eq!(Some((99, 1)), range_map.get_with_rule(97));
+    assert_eq!(Some((50, 2)), range_map.get_with_rule(98));
+    assert_eq!(Some((51, 2)), range_map.get_with_rule(99));
+    assert_eq!(None, range_map.get_with_rule(100));
+    assert_eq!(Some(&(52, 50, 48)), range_map.iter().nth(1));
+}
+
+#[test]
+fn test_get_many() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48), (u64::MAX, 200, 5)]);
+    let values = [99, 10, 50, 200, 98, 97, 201, 50];
+    let expected: Vec<Option<u64>> = values.iter().map(|&value| range_map.get(value)).collect();
+
+    assert_eq!(
+        vec![Some(51), None, Some(52), Some(u64::MAX), Some(50), Some(99), None, Some(52)],
+        range_map.get_many(&values)
+    );
+    assert_eq!(expected, range_map.get_many(&values));
+    assert_eq!(Vec::<Option<u64>>::new(), range_map.get_many(&[]));
+    assert_eq!(vec![None, None], RangeMap::default().get_many(&[0, u64::MAX]));
+}
+
+#[test]
+fn test_union() {
+    let base = RangeMap::new(vec![(100, 0, 10), (200, 20, 10)]);
+
+    // A patch inside a base rule splits it.
+    let inside = RangeMap::new(vec![(500, 3, 2)]);
+    let patched = base.union(&inside, Precedence::Right);
+    assert_eq!(&[(100, 0, 3), (500, 3, 2), (105, 5, 5), (200, 20, 10)], patched.ranges());
+    assert_eq!(base, base.union(&inside, Precedence::Left));
+
+    // A patch straddling two rules and the gap between them.
+    let straddling = RangeMap::new(vec![(500, 8, 15)]);
+    let patched = base.union(&straddling, Precedence::Right);
+    assert_eq!(&[(100, 0, 8), (500, 8, 15), (203, 23, 7)], patched.ranges());
+    assert_eq!(&[(100, 0, 10), (502, 10, 10), (200, 20, 10)], base.union(&straddling, Precedence::Left).ranges());
+    for value in 0..40 {
+        let expected = if (8..23).contains(&value) { straddling.get(value) } else { base.get(value) };
+        assert_eq!(expected, patched.get(value), "{}", value);
+    }
+
+    // A patch in a gap adds to the base whichever side wins.
+    let gap = RangeMap::new(vec![(500, 12, 4)]);
+    assert_eq!(&[(100, 0, 10), (500, 12, 4), (200, 20, 10)], base.union(&gap, Precedence::Left).ranges());
+    assert_eq!(base.union(&gap, Precedence::Left), base.union(&gap, Precedence::Right));
+
+    for precedence in [Precedence::Left, Precedence::Right] {
+        assert!(base.union(&straddling, precedence).validate().is_ok());
+        assert_eq!(base, base.union(&RangeMap::default(), precedence));
+        assert_eq!(base, RangeMap::default().union(&base, precedence));
+    }
+}
+
+#[test]
+fn test_intersect_domains() {
+    let old = RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]);
+    // Agrees on 60..70 and 90..98, moves 50..60 and 70..90 elsewhere, and drops 98..100.
+    let new = RangeMap::new(vec![(0, 40, 20), (62, 60, 10), (200, 70, 20), (92, 90, 8), (300, 120, 5)]);
+
+    assert_eq!(vec![(50, 48)], old.intersect_domains(&new));
+    assert_eq!(old.intersect_domains(&new), new.intersect_domains(&old));
+    assert_eq!(vec![(50, 10), (70, 20)], old.differs_on(&new));
+    assert_eq!(old.differs_on(&new), new.differs_on(&old));
+    for value in 0..130 {
+        let both = old.contains(value) && new.contains(value);
+        let differs = old.differs_on(&new).iter().any(|&(start, size)| start <= value && value < start + size);
+        assert_eq!(both && old.get(value) != new.get(value), differs, "{}", value);
+    }
+
+    assert_eq!(Vec::<(u64, u64)>::new(), old.differs_on(&old));
+    assert_eq!(vec![(50, 50)], old.intersect_domains(&old));
+    assert_eq!(Vec::<(u64, u64)>::new(), old.intersect_domains(&RangeMap::default()));
+}
+
+#[test]
+fn test_equivalent() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]);
+    let mut normalized = RangeMap::new(vec![(52, 50, 10), (62, 60, 38), (50, 98, 2)]);
+    normalized.normalize();
+    assert!(range_map.equivalent(&normalized, (0, u64::MAX)));
+    // An identity rule changes nothing.
+    assert!(range_map.equivalent(&range_map.to_total(200), (0, u64::MAX)));
+
+    // The two maps only differ on 97: 97 -> 99 against 97 -> 97.
+    let shorter = RangeMap::new(vec![(50, 98, 2), (52, 50, 47)]);
+    assert!(!range_map.equivalent(&shorter, (0, 200)));
+    assert!(!range_map.equivalent(&shorter, (97, 1)));
+    assert!(range_map.equivalent(&shorter, (0, 97)));
+    assert!(range_map.equivalent(&shorter, (98, 100)));
+    assert!(range_map.equivalent(&shorter, (97, 0)));
+
+    // Composing maps does not change where values go.
+    let next = RangeMap::new(vec![(0, 15, 37), (37, 52, 2), (39, 0, 15)]);
+    let composed = range_map.compose(&next);
+    let chained = RangeMap::new(
+        (0..120).map(|value| (next.get_or_identity(range_map.get_or_identity(value)), value, 1)).collect()
+    );
+    assert!(composed.equivalent(&chained, (0, 120)));
+    let mut off_by_one = chained.clone();
+    off_by_one.push((1000, 60, 1));
+    assert!(!composed.equivalent(&off_by_one, (0, 120)));
+}
+
+#[test]
+fn test_coverage() {
+    let empty = RangeMap::<u64>::default();
+    assert!(!empty.contains(0));
+    assert_eq!(0, empty.covered_size());
+    assert_eq!((None, None), (empty.min_source(), empty.max_source()));
+
+    let single = RangeMap::new(vec![(52, 50, 48)]);
+    assert!(!single.contains(49));
+    assert!(single.contains(50) && single.contains(97));
+    assert!(!single.contains(98));
+    assert_eq!(48, single.covered_size());
+    assert_eq!((Some(50), Some(97)), (single.min_source(), single.max_source()));
+
+    // 5..10 lies inside 0..20 and 15..30 overlaps it, so 0..30 and 40..41 count once: 31, not 41.
+    let overlapping = RangeMap::new(vec![(100, 0, 20), (200, 5, 5), (300, 15, 15), (400, 40, 1)]);
+    assert_eq!(31, overlapping.covered_size());
+    assert!(overlapping.contains(12));
+    assert_eq!(Some(112), overlapping.get(12));
+    assert!(!overlapping.contains(30) && !overlapping.contains(39));
+    assert_eq!((Some(0), Some(40)), (overlapping.min_source(), overlapping.max_source()));
+}
+
+#[test]
+fn test_restrict() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]);
+    let domain = [(40, 15), (79, 14), (85, 3), (99, 10)];
+    let restricted = range_map.restrict(&domain);
+
+    assert_eq!(RangeMap::new(vec![(52, 50, 5), (81, 79, 14), (51, 99, 1)]), restricted);
+    let in_domain = |value: u64| domain.iter().any(|&(start, size)| start <= value && value < start + size);
+    for value in 0..120 {
+        match in_domain(value) {
+            true => assert_eq!(range_map.get(value), restricted.get(value), "{}", value),
+            false => assert_eq!(None, restricted.get(value), "{}", value),
+        }
+    }
+    assert_eq!(Some(52), restricted.get(50));
+    assert_eq!(Some(56), restricted.get(54));
+    assert_eq!(None, restricted.get(55));
+    assert_eq!(None, restricted.get(98));
+    assert!(range_map.restrict(&[]).is_empty());
+    assert!(range_map.restrict(&[(0, 50), (100, 10)]).is_empty());
+}
+
+#[test]
+fn test_without_identity() {
+    let range_map = RangeMap::new(vec![(10, 10, 5), (50, 98, 2), (0, 0, 3), (20, 15, 5)]);
+    let stripped = range_map.without_identity();
+
+    assert_eq!(&[(20, 15, 5), (50, 98, 2)], stripped.ranges());
+    for value in 0..110 {
+        assert_eq!(range_map.get_or_identity(value), stripped.get_or_identity(value), "{}", value);
+    }
+    assert_eq!(range_map.without_identity(), range_map.to_total(200).without_identity());
+}
+
+#[test]
+fn test_to_total() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48), (0, 120, 30)]);
+    let total = range_map.to_total(130);
+
+    assert_eq!(&[(0, 0, 50), (52, 50, 48), (50, 98, 2), (100, 100, 20), (0, 120, 30)], total.ranges());
+    assert_eq!(Ok(()), total.validate());
+    let boundaries = range_map.ranges().iter().flat_map(|&(_, source, size)| [source, source + size]);
+    for value in boundaries.chain([0, 130]).flat_map(|v| [v.saturating_sub(1), v, v + 1]).filter(|&v| v < 150) {
+        assert_eq!(range_map.get_or_identity(value), total.get(value).unwrap(), "{}", value);
+    }
+    assert_eq!(None, total.get(150));
+    assert_eq!(&[(0, 0, 10)], RangeMap::<u64>::default().to_total(10).ranges());
+    assert!(RangeMap::<u64>::default().to_total(0).is_empty());
+}
+
+#[test]
+fn test_get_ranges_visits_only_the_window() {
+    // 200k rules, yet each query only looks at the few rules around its interval.
+    let mut range_map = RangeMap::new((0..200_000).map(|i| ((i * 7_919) % 200_000 * 10, i * 10, 7)).collect());
+
+    let naive = |start: u64, size: u64| {
+        let mut values: Vec<u64> = (start..start + size)
+            .map(|value| {
+                range_map
+                    .ranges()
+                    .iter()
+                    .find(|&&(_, source, size)| source <= value && value < source + size)
+                    .map_or(value, |&(destination, source, _)| destination + value - source)
+            })
+            .collect();
+        values.sort();
+        values
+    };
+    for (start, size) in [(0, 25), (1_000_500, 40), (1_999_990, 30), (999_995, 20), (2_500_000, 10)] {
+        assert!(range_map.window(start, start + size).len() <= 5, "{}", start);
+        let mut values: Vec<u64> = range_map
+            .get_ranges(start, size)
+            .into_iter()
+            .flat_map(|(output, output_size)| output..output + output_size)
+            .collect();
+        values.sort();
+        assert_eq!(naive(start, size), values, "{}", start);
+    }
+
+    // A long rule overlapping a hundred others is still found from anywhere inside it, at the cost
+    // of widening the window back to it.
+    range_map.push((5_000_000, 1_000_000, 1_000));
+    assert_eq!(91, range_map.window(1_000_908, 1_000_909).len());
+    assert!(range_map.contains(1_000_908));
+    assert!(!range_map.contains(1_001_008));
+    assert!(range_map.window(3_000_000, 3_000_001).is_empty());
+    let naive_get = |value: u64| {
+        range_map
+            .ranges()
+            .iter()
+            .filter(|&&(_, source, size)| source <= value && value < source + size)
+            .max_by_key(|&&(_, source, _)| source)
+            .map(|&(destination, source, _)| destination + value - source)
+    };
+    for value in (999_990..1_001_020).step_by(3) {
+        assert_eq!(naive_get(value), range_map.get(value), "{}", value);
+    }
+    assert_eq!(Some(5_000_908), range_map.get(1_000_908));
+}
+
+#[test]
+fn test_preimage_ranges() {
+    let range_map = RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]);
+
+    // 49..53 comes from 49 (identity), 98..100 and 50..51 (rules).
+    assert_eq!(vec![(98, 2), (50, 1), (49, 1)], range_map.preimage_ranges(&[(49, 4)]));
+    // 98..101 comes from 96..98 and from 100 itself, but not from 98..100, which a rule moves away.
+    assert_eq!(vec![(96, 2), (100, 1)], range_map.preimage_ranges(&[(98, 3)]));
+    assert!(range_map.preimage_ranges(&[]).is_empty());
+}
+
+#[test]
+fn test_ranges_of_overflowing_rules() {
+    let max = u64::MAX;
+    let beyond = RangeMap::new(vec![(max, 0, 10)]);
+    assert_eq!(None, beyond.get(5));
+    assert_eq!(vec![(5, 1)], beyond.get_ranges(5, 1));
+    assert_eq!(vec![(0, 10)], beyond.map_ranges(0, 10).collect::<Vec<_>>());
+
+    // 0..5 fits below `max`, the rest is left to identity.
+    let straddling = RangeMap::new(vec![(max - 5, 0, 10), (max - 20, 20, 10)]);
+    assert_eq!(Some(max - 2), straddling.get(3));
+    assert_eq!(None, straddling.get(6));
+    assert_eq!(vec![(5, 5), (max - 5, 5)], straddling.get_ranges(0, 10));
+    assert_eq!(vec![(5, 15), (max - 20, 10), (max - 5, 5)], straddling.map_ranges(0, 30).collect::<Vec<_>>());
+    assert_eq!(vec![(max - 5, 5), (5, 15), (max - 20, 10)], straddling.partition(0, 30));
+    let composed = straddling.compose(&RangeMap::default());
+    assert_eq!(straddling.get_ranges(0, 40), composed.get_ranges(0, 40));
+    assert_eq!(vec![3, max - 2], straddling.preimages(max - 2));
+    assert_eq!(Ok(()), straddling.validate());
+}
+
+#[test]
+fn test_get_ranges_into() {
+    let range_map = RangeMap::new(vec![(1000, 100, 100), (2000, 300, 50), (0, 400, 10)]);
+
+    let mut out = vec![(7, 1)];
+    range_map.get_ranges_into(50, 400, &mut out);
+    assert_eq!((7, 1), out[0]);
+    assert_eq!(range_map.get_ranges(50, 400), out[1..]);
+    range_map.get_ranges_into(0, 1000, &mut out);
+    assert_eq!([range_map.get_ranges(50, 400), range_map.get_ranges(0, 1000)].concat(), out[1..]);
+}
+
+#[test]
+fn test_map_ranges() {
+    let range_map = RangeMap::new(vec![(1000, 100, 100), (2000, 300, 50), (0, 400, 10)]);
+    let overlapping = RangeMap::new(vec![(500, 0, 10), (100, 5, 10), (900, 30, 5)]);
+    let at_the_end = RangeMap::new(vec![(0, u64::MAX - 10, 10)]);
+
+    // Maps every value of the query on its own, through the first rule covering it in source order.
+    let naive = |range_map: &RangeMap, start: u64, size: u64| {
+        let mut values: Vec<u64> = (start..start.saturating_add(size))
+            .map(|value| {
+                range_map
+                    .ranges()
+                    .iter()
+                    .find(|&&(_, source, size)| source <= value && value - source < size)
+                    .map_or(value, |&(destination, source, _)| destination + (value - source))
+            })
+            .collect();
+        values.sort_unstable();
+        values
+    };
+
+    for range_map in [&range_map, &overlapping, &at_the_end, &RangeMap::default()] {
+        for (start, size) in [(50, 400), (0, 1000), (120, 10), (0, 0), (7, 30), (u64::MAX - 20, 50)] {
+            let mapped: Vec<(u64, u64)> = range_map.map_ranges(start, size).collect();
+            assert!(mapped.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", mapped);
+            let mut values: Vec<u64> = mapped.iter().flat_map(|&(start, size)| start..start + size).collect();
+            values.sort_unstable();
+            assert_eq!(naive(range_map, start, size), values, "{} {}", start, size);
+        }
+    }
+
+    // Rule portions and gaps come sorted by output start, whatever their source order.
+    let mut lazy = range_map.map_ranges(50, 400);
+    assert_eq!(Some((0, 10)), lazy.next());
+    assert_eq!(Some((50, 50)), lazy.next());
+    assert_eq!(vec![(200, 100), (350, 50), (410, 40), (1000, 100), (2000, 50)], lazy.collect::<Vec<_>>());
+    assert_eq!(Some((15, 15)), overlapping.map_ranges(5, 30).next());
+}
+
+#[test]
+fn test_get_ranges_partition() {
+    let range_map = RangeMap::new(vec![(1000, 100, 100), (2000, 300, 50), (0, 400, 10)]);
+
+    let (mapped, unmapped) = range_map.get_ranges_partition(50, 400);
+    assert_eq!(vec![(0, 10), (1000, 100), (2000, 50)], mapped);
+    assert_eq!(vec![(50, 50), (200, 100), (350, 50), (410, 40)], unmapped);
+
+    for (start, size) in [(50, 400), (0, 1000), (120, 10), (200, 100), (399, 2)] {
+        let (mapped, unmapped) = range_map.get_ranges_partition(start, size);
+        let restricted = range_map.restrict(&[(start, size)]);
+        assert_eq!(mapped.len(), restricted.len());
+        let mut tiles: Vec<(u64, u64)> = restricted
+            .iter()
+            .map(|&(_, source, size)| (source, size))
+            .chain(unmapped.iter().copied())
+            .collect();
+        tiles.sort();
+        let mut cursor = start;
+        for (tile_start, tile_size) in tiles {
+            assert_eq!(cursor, tile_start, "{:?}", (start, size));
+            cursor += tile_size;
+        }
+        assert_eq!(start + size, cursor);
+        assert!(crate::same_ranges(&range_map.get_ranges(start, size), &[mapped, unmapped].concat()));
+    }
+}
+
+#[test]
+fn test_destination_overlaps() {
+    // Sources 0..10, 20..30 and 40..45 are disjoint, but 20..30 and 40..45 land on 105..110.
+    let range_map = RangeMap::new(vec![(100, 0, 10), (105, 20, 10), (200, 40, 5), (103, 60, 1)]);
+
+    assert_eq!(Ok(()), range_map.validate());
+    assert_eq!(vec![(0, 1, (105, 5)), (0, 3, (103, 1))], range_map.destination_overlaps());
+    assert!(!range_map.is_injective());
+
+    assert!(RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]).is_injective());
+    assert!(RangeMap::new(vec![(10, 0, 5), (15, 5, 5), (0, 10, 0)]).is_injective());
+    assert!(RangeMap::<u64>::default().is_injective());
+}
+
+#[test]
+fn test_values_above_u32() {
+    let big = u64::from(u32::MAX) + 1;
+    let range_map = RangeMap::new(vec![(big * 3, big, 10), (0, big * 2, big)]);
+
+    assert_eq!(Some(big * 3 + 4), range_map.get(big + 4));
+    assert_eq!(Some(big - 1), range_map.get(big * 3 - 1));
+    assert_eq!(vec![(big - 2, 2), (big + 10, 5), (big * 3, 10)], range_map.get_ranges(big - 2, 17));
+    assert_eq!(range_map, RangeMap::from(vec![(3usize << 32, 1 << 32, 10), (0, 2 << 32, 1 << 32)]));
+}
+
+#[test]
+fn test_u128_values() {
+    let big = u128::from(u64::MAX) + 1;
+    let range_map = RangeMap::<u128>::from_rules(vec![(big * 4, big * 2, 100), (10, big * 3, 50)]);
+
+    assert_eq!(Some(big * 4 + 5), range_map.get(big * 2 + 5));
+    assert_eq!(None, range_map.get(big * 2 + 100));
+    assert_eq!(Some(12), range_map.get(big * 3 + 2));
+    assert_eq!(vec![(big * 2 - 10, 10), (big * 4, 20)], range_map.get_ranges(big * 2 - 10, 30));
+    assert_eq!(Some(big * 2 + 5), range_map.invert().get(big * 4 + 5));
+
+    let next = RangeMap::from_rules(vec![(0, big * 4, 10)]);
+    let composed = range_map.compose(&next);
+    assert_eq!(3, composed.get_or_identity(big * 2 + 3));
+    assert_eq!(big * 4 + 50, composed.get_or_identity(big * 2 + 50));
+    assert_eq!(12, composed.get_or_identity(big * 3 + 2));
+
+    // Destinations past u128::MAX are not mapped rather than wrapping, as with u64.
+    let edge = RangeMap::<u128>::from_rules(vec![(u128::MAX - 5, 0, 10)]);
+    assert_eq!(Some(u128::MAX), edge.get(5));
+    assert_eq!(None, edge.get(6));
+    assert_eq!(vec![(u128::MAX - 20, 20)], edge.gaps((u128::MAX - 20, 50)));
+}
@@ -0,0 +1,84 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::parse::check_rule;
+use crate::{MapValue, RangeMap, RangeMapChain};
+
+#[derive(Serialize, Deserialize)]
+struct Rule<T> {
+    destination: T,
+    source: T,
+    size: T,
+}
+
+#[derive(Serialize)]
+struct StageRef<'a> {
+    source: &'a str,
+    label: &'a str,
+    map: &'a RangeMap,
+}
+
+#[derive(Deserialize)]
+struct Stage {
+    #[serde(default)]
+    source: Option<String>,
+    label: String,
+    map: RangeMap,
+}
+
+/// Serialized as a list of `{destination, source, size}` objects.
+impl<T: MapValue + Serialize> Serialize for RangeMap<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.ranges()
+                .iter()
+                .map(|&(destination, source, size)| Rule { destination, source, size })
+        )
+    }
+}
+
+impl<'de, T: MapValue + Deserialize<'de>> Deserialize<'de> for RangeMap<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rules = Vec::<Rule<T>>::deserialize(deserializer)?;
+        let rules = rules
+            .into_iter()
+            .map(|Rule { destination, source, size }| {
+                check_rule((destination, source, size)).map_err(|kind| {
+                    D::Error::custom(format!("rule `{} {} {}`: {}", destination, source, size, kind))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(RangeMap::from_rules(rules))
+    }
+}
+
+/// Serialized as an ordered list of `{source, label, map}` objects.
+impl Serialize for RangeMapChain {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.range_maps()
+                .iter()
+                .enumerate()
+                .map(|(index, (label, map))| StageRef { source: self.source(index).unwrap_or_default(), label, map })
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for RangeMapChain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stages = Vec::<Stage>::deserialize(deserializer)?;
+        // A stage without a source resolves from the label of the stage before it.
+        let mut previous = String::from("seed");
+        RangeMapChain::from_stages(
+            stages
+                .into_iter()
+                .map(|stage| {
+                    let source = stage.source.unwrap_or_else(|| previous.clone());
+                    previous = stage.label.clone();
+                    (source, stage.label, stage.map)
+                })
+                .collect()
+        )
+        .map_err(D::Error::custom)
+    }
+}
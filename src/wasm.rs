@@ -0,0 +1,10 @@
+//! JavaScript bindings, for building the solver to WebAssembly with `wasm-pack`.
+
+use wasm_bindgen::prelude::*;
+
+/// Solves part 1 or 2 of the puzzle for the text of an almanac, as `solve_from_str` does. The
+/// lowest location is returned as a `BigInt`, and errors are thrown as strings.
+#[wasm_bindgen(js_name = solveFromStr)]
+pub fn solve_from_str(input: &str, part: u8) -> Result<u64, JsValue> {
+    crate::solve_from_str(input, part).map_err(JsValue::from)
+}
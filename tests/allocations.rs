@@ -0,0 +1,80 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use aoc2023_day05::generate::{generate, GeneratorConfig};
+use aoc2023_day05::Resolver;
+
+/// Counts the allocations made by the current thread, so tests running in parallel don't
+/// disturb each other's counts.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs `f` and returns its result with the number of allocations it made.
+fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn test_resolver_reuses_its_buffers() {
+    let almanac = generate(&GeneratorConfig { rules_per_stage: 40, ..GeneratorConfig::default() });
+    let seed_ranges = almanac.seed_ranges().unwrap();
+    let chain = &almanac.chain;
+
+    let mut resolver = Resolver::new();
+    for &range in &seed_ranges {
+        chain.resolve_ranges_with(&[range], "location", &mut resolver).unwrap();
+    }
+    chain.resolve_ranges_with(&seed_ranges, "location", &mut resolver).unwrap();
+
+    let (expected, fresh) = count_allocations(|| chain.resolve_ranges(&seed_ranges, "location").unwrap());
+    assert!(fresh > 0);
+    let (reused, allocations) = count_allocations(|| {
+        let mut reused = 0;
+        for &range in &seed_ranges {
+            let resolved = chain.resolve_ranges_with(&[range], "location", &mut resolver).unwrap();
+            reused += resolved.len();
+        }
+        reused
+    });
+    assert!(reused > 0);
+    assert_eq!(0, allocations);
+
+    let resolved = chain.resolve_ranges_with(&seed_ranges, "location", &mut resolver).unwrap();
+    assert_eq!(expected, resolved);
+}
+
+#[test]
+fn test_get_ranges_into_does_not_allocate() {
+    let almanac = generate(&GeneratorConfig { stages: 1, rules_per_stage: 1_000, ..GeneratorConfig::default() });
+    let (_, range_map) = &almanac.chain.range_maps()[0];
+    let expected = range_map.get_ranges(1 << 30, 1 << 31);
+
+    let mut out = Vec::with_capacity(expected.len());
+    let ((), allocations) = count_allocations(|| range_map.get_ranges_into(1 << 30, 1 << 31, &mut out));
+    assert_eq!(0, allocations);
+    assert_eq!(expected, out);
+}
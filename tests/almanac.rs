@@ -0,0 +1,330 @@
+use aoc2023_day05::{parse_almanac, solve_from_str, Almanac, OddSeedCount, VerifyError};
+
+const EXAMPLE: &str = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4
+";
+
+#[test]
+fn test_example_answers() {
+    let almanac = Almanac::parse(EXAMPLE).unwrap();
+
+    assert_eq!(vec![79, 14, 55, 13], almanac.seeds);
+    assert_eq!(7, almanac.chain.range_maps().len());
+    assert_eq!(Some(35), almanac.lowest_location());
+    assert_eq!(Ok(Some(46)), almanac.lowest_location_for_ranges());
+    assert_eq!(Some((13, 35)), almanac.lowest_seed());
+    assert_eq!(Ok(Some((82, 46))), almanac.lowest_seed_in_ranges());
+    assert_eq!(almanac, parse_almanac(EXAMPLE).unwrap());
+}
+
+#[test]
+fn test_solve_from_str() {
+    assert_eq!(Ok(35), solve_from_str(EXAMPLE, 1));
+    assert_eq!(Ok(46), solve_from_str(EXAMPLE, 2));
+    assert_eq!(Err(String::from("invalid part `3`, expected 1 or 2")), solve_from_str(EXAMPLE, 3));
+    assert_eq!(
+        Err(String::from("part 2 requires an even number of seed values, found 3")),
+        solve_from_str("seeds: 1 2 3\n\na-to-location map:\n10 0 5\n", 2)
+    );
+    assert!(solve_from_str("seeds: x", 1).unwrap_err().starts_with("line 1: invalid number"));
+}
+
+#[test]
+fn test_brute_force_matches_ranges() {
+    let almanac = Almanac::parse(EXAMPLE).unwrap();
+
+    assert_eq!(almanac.lowest_seed_in_ranges(), almanac.lowest_seed_in_ranges_brute_force());
+}
+
+#[test]
+fn test_odd_seed_count() {
+    let almanac = Almanac::parse("seeds: 1 2 3\n\na-to-location map:\n10 0 5\n").unwrap();
+
+    assert_eq!(Some(11), almanac.lowest_location());
+    assert_eq!(Err(OddSeedCount { found: 3 }), almanac.seed_ranges());
+    let error = almanac.lowest_location_for_ranges().unwrap_err();
+    assert_eq!("part 2 requires an even number of seed values, found 3", error.to_string());
+}
+
+#[test]
+fn test_display_round_trip() {
+    let almanac = Almanac::parse(EXAMPLE).unwrap();
+    let displayed = almanac.to_string();
+    let reparsed = Almanac::parse(&displayed).unwrap();
+
+    assert_eq!(almanac, reparsed);
+    assert_eq!(almanac.lowest_location(), reparsed.lowest_location());
+    assert_eq!(almanac.lowest_location_for_ranges(), reparsed.lowest_location_for_ranges());
+    // Rules are written back sorted by source, so the text only differs in rule order.
+    let sorted_lines = |text: &str| {
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        lines.sort();
+        lines
+    };
+    assert_eq!(sorted_lines(EXAMPLE), sorted_lines(&displayed));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_from_file_mmap() {
+    use aoc2023_day05::ParseOptions;
+
+    for path in ["tests/data/example.txt", "tests/data/example_messy.txt", "tests/data/shuffled.txt"] {
+        let file = std::fs::File::open(path).unwrap();
+        let mapped = Almanac::from_file_mmap(&file, &ParseOptions::default()).unwrap();
+        assert_eq!(Almanac::parse(EXAMPLE).unwrap(), mapped, "{}", path);
+    }
+    let file = std::fs::File::open("tests/data/overlapping.txt").unwrap();
+    let strict = ParseOptions { strict: true, ..ParseOptions::default() };
+    assert_eq!(
+        Almanac::from_reader_strict(std::io::BufReader::new(&file)).unwrap_err().to_string(),
+        Almanac::from_file_mmap(&file, &strict).unwrap_err().to_string()
+    );
+
+    let empty = format!("{}/empty.txt", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&empty, "").unwrap();
+    let error = Almanac::from_file_mmap(&std::fs::File::open(&empty).unwrap(), &ParseOptions::default()).unwrap_err();
+    assert_eq!("line 1: unexpected end of input, expected a seeds line", error.to_string());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let almanac = Almanac::parse(EXAMPLE).unwrap();
+    let json = serde_json::to_string(&almanac).unwrap();
+    let deserialized: Almanac = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(almanac, deserialized);
+    assert_eq!(
+        almanac.chain.resolve_ranges(&almanac.seed_ranges().unwrap(), "location").unwrap().iter().min(),
+        deserialized.chain.resolve_ranges(&deserialized.seed_ranges().unwrap(), "location").unwrap().iter().min()
+    );
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!("soil", value["chain"][0]["label"]);
+    assert_eq!(serde_json::json!({"destination": 52, "source": 50, "size": 48}), value["chain"][0]["map"][0]);
+
+    let empty_label = r#"[{"label": "", "map": []}]"#;
+    assert!(serde_json::from_str::<aoc2023_day05::RangeMapChain>(empty_label).is_err());
+    let negative_size = r#"[{"destination": 1, "source": 2, "size": -3}]"#;
+    assert!(serde_json::from_str::<aoc2023_day05::RangeMap>(negative_size).is_err());
+
+    // Rules are checked as the text parser checks them: empty rules are fine, overflowing ones are not.
+    let empty = r#"[{"destination": 1, "source": 2, "size": 0}]"#;
+    assert_eq!(Almanac::parse("seeds: 1\n\nseed-to-soil map:\n1 2 0\n").unwrap().chain.range_maps()[0].1,
+        serde_json::from_str::<aoc2023_day05::RangeMap>(empty).unwrap());
+    let overflowing = format!(r#"[{{"destination": {}, "source": 0, "size": 10}}]"#, u64::MAX - 5);
+    let error = serde_json::from_str::<aoc2023_day05::RangeMap>(&overflowing).unwrap_err().to_string();
+    assert!(
+        error.starts_with("rule `18446744073709551610 0 10`: rule overflows, source + size and destination + size"),
+        "{}", error
+    );
+}
+
+#[test]
+fn test_from_reader() {
+    let from_cursor = Almanac::from_reader(std::io::Cursor::new(EXAMPLE)).unwrap();
+    let file = std::fs::File::open("tests/data/example.txt").unwrap();
+    let from_file = Almanac::from_reader(std::io::BufReader::new(file)).unwrap();
+
+    assert_eq!(from_cursor, from_file);
+    assert_eq!(Almanac::parse(EXAMPLE).unwrap(), from_file);
+}
+
+#[test]
+fn test_commented_example() {
+    let almanac = Almanac::parse(include_str!("data/example_messy.txt")).unwrap();
+
+    assert_eq!(Almanac::parse(EXAMPLE).unwrap(), almanac);
+    assert_eq!(Some(35), almanac.lowest_location());
+    assert_eq!(Ok(Some(46)), almanac.lowest_location_for_ranges());
+}
+
+#[test]
+fn test_shuffled_sections() {
+    let almanac = Almanac::parse(include_str!("data/shuffled.txt")).unwrap();
+
+    assert_eq!(Almanac::parse(EXAMPLE).unwrap(), almanac);
+    assert_eq!(Some("seed"), almanac.chain.source(0));
+    assert_eq!(Some((13, 35)), almanac.lowest_seed());
+    assert_eq!(Ok(Some((82, 46))), almanac.lowest_seed_in_ranges());
+}
+
+#[test]
+fn test_multiline_seeds() {
+    let maps = EXAMPLE.split_once('\n').unwrap().1;
+    for seeds in ["seeds: 79 14\n55 13\n", "seeds: 79\n14 55\n13\n", "seeds:\n\n79 14\n55 13\n"] {
+        let almanac = Almanac::parse(&format!("{}{}", seeds, maps)).unwrap();
+
+        assert_eq!(vec![79, 14, 55, 13], almanac.seeds, "{:?}", seeds);
+        assert_eq!(Some((13, 35)), almanac.lowest_seed());
+        assert_eq!(Ok(Some((82, 46))), almanac.lowest_seed_in_ranges());
+    }
+}
+
+#[test]
+fn test_crlf_and_bom() {
+    let windows = format!("\u{feff}{}", EXAMPLE.replace('\n', "\r\n"));
+    let almanac = Almanac::parse(&windows).unwrap();
+
+    assert_eq!(Almanac::parse(EXAMPLE).unwrap(), almanac);
+    assert_eq!(Some(35), almanac.lowest_location());
+    assert_eq!(Ok(Some(46)), almanac.lowest_location_for_ranges());
+    assert_eq!(almanac, Almanac::from_reader(windows.as_bytes()).unwrap());
+}
+
+#[test]
+fn test_json_input() {
+    let almanac = Almanac::from_json_reader(std::fs::File::open("tests/data/example.json").unwrap()).unwrap();
+
+    assert_eq!(Almanac::parse(EXAMPLE).unwrap(), almanac);
+    assert_eq!(Some(35), almanac.lowest_location());
+    assert_eq!(Ok(Some(46)), almanac.lowest_location_for_ranges());
+}
+
+#[test]
+fn test_boundary_matches_ranges() {
+    let almanac = Almanac::parse(EXAMPLE).unwrap();
+    assert_eq!(Ok(Some((82, 46))), almanac.lowest_seed_in_ranges_boundary());
+}
+
+#[test]
+fn test_brute_force_progress() {
+    let almanac = Almanac::parse(&EXAMPLE.replace("seeds: 79 14 55 13", "seeds: 79 14 100 3000000")).unwrap();
+    let reports = std::sync::Mutex::new(Vec::new());
+
+    let lowest = almanac
+        .lowest_seed_in_ranges_brute_force_with_progress(|progress| reports.lock().unwrap().push(progress.clone()))
+        .unwrap();
+    assert_eq!(almanac.lowest_seed_in_ranges_brute_force().unwrap(), lowest);
+
+    let reports = reports.into_inner().unwrap();
+    // 14 seeds in one batch, and 3000000 split into batches of 2^20.
+    assert_eq!(1 + 3_000_000u64.div_ceil(aoc2023_day05::BRUTE_FORCE_BATCH) as usize, reports.len());
+    assert!(reports.iter().all(|progress| progress.total == 3_000_014));
+    assert!(reports.windows(2).all(|pair| pair[0].done < pair[1].done));
+    assert_eq!(3_000_014, reports.last().unwrap().done);
+    assert_eq!(lowest, reports.last().unwrap().best);
+}
+
+#[test]
+fn test_verify() {
+    let almanac = Almanac::parse(EXAMPLE).unwrap();
+    assert_eq!(Ok(Some(46)), almanac.verify(None));
+    assert_eq!(Ok(Some(46)), almanac.verify(Some(3)));
+
+    // Ranges produced by a chain whose humidity-to-location map is off by one.
+    let buggy = Almanac::parse(&EXAMPLE.replace("60 56 37", "61 56 37")).unwrap();
+    let resolved = buggy.chain.resolve_ranges(&almanac.seed_ranges().unwrap(), "location").unwrap();
+    assert_eq!(
+        Err(VerifyError::Unreached { seed: 55, location: 86 }),
+        almanac.verify_ranges(&resolved, None)
+    );
+    assert!(almanac.verify_ranges(&resolved, Some(2)).is_err());
+
+    // Ranges that contain every location but start too low.
+    let mut widened = almanac.chain.resolve_ranges(&almanac.seed_ranges().unwrap(), "location").unwrap();
+    widened.insert(0, (10, 5));
+    assert_eq!(
+        Err(VerifyError::Minimum { found: Some(10), resolved: Some(46) }),
+        almanac.verify_ranges(&widened, None)
+    );
+    assert_eq!(Ok(()), almanac.verify_ranges(&widened, Some(5)));
+    assert_eq!(
+        "the lowest location found is 10 but resolving seeds one at a time gives 46",
+        almanac.verify_ranges(&widened, None).unwrap_err().to_string()
+    );
+
+    assert_eq!(Ok(()), almanac.verify_minimum(Some(46), None));
+    assert_eq!(Ok(()), almanac.verify_minimum(Some(46), Some(3)));
+    assert_eq!(
+        Err(VerifyError::Minimum { found: Some(10), resolved: Some(46) }),
+        almanac.verify_minimum(Some(10), None)
+    );
+    // A sample may miss the lowest seed, but none of its seeds may land below the minimum.
+    assert_eq!(Ok(()), almanac.verify_minimum(Some(10), Some(3)));
+    assert!(almanac.verify_minimum(Some(47), Some(14)).is_err());
+    assert!(almanac.verify_minimum(None, None).is_err());
+}
+
+#[test]
+fn test_overlapping_seed_ranges() {
+    // 79..93 again, a range nested in it, one overlapping its end and one adjacent to 55..68.
+    let seeds = "seeds: 79 14 55 13 80 5 90 10 68 2";
+    let almanac = Almanac::parse(&EXAMPLE.replace("seeds: 79 14 55 13", seeds)).unwrap();
+    assert_eq!(Ok(vec![(55, 15), (79, 21)]), almanac.normalized_seed_ranges());
+
+    let normalized = almanac.normalized_seed_ranges().unwrap();
+    let seeds: Vec<u64> = normalized.iter().flat_map(|&(start, size)| start..start + size).collect();
+    let lowest = seeds
+        .iter()
+        .map(|&seed| (seed, almanac.chain.resolve(seed, "location").unwrap()))
+        .min_by_key(|&(_, location)| location);
+    assert_eq!(Ok(lowest), almanac.lowest_seed_in_ranges());
+    assert_eq!(Ok(lowest), almanac.lowest_seed_in_ranges_boundary());
+    assert_eq!(Ok(lowest), almanac.lowest_seed_in_ranges_brute_force());
+    assert_eq!(Ok(lowest.map(|(_, location)| location)), almanac.lowest_location_for_ranges());
+    assert_eq!(Ok(lowest.map(|(_, location)| location)), almanac.verify(None));
+
+    // Every seed is counted once, however many input ranges hold it.
+    let seed_ranges = almanac.seed_ranges().unwrap();
+    let reports = std::sync::Mutex::new(Vec::new());
+    almanac
+        .lowest_seed_in_ranges_brute_force_with_progress(|progress| reports.lock().unwrap().push(progress.clone()))
+        .unwrap();
+    assert_eq!(36, reports.into_inner().unwrap()[0].total);
+    assert_eq!(36, almanac.chain.count_locations(&seed_ranges));
+    let histogram: u64 = almanac.chain.location_histogram(&seed_ranges, 10).iter().map(|&(_, count)| count).sum();
+    assert_eq!(36, histogram);
+    let mut locations = almanac.chain.resolve_many(&seeds, "location").unwrap();
+    locations.sort_unstable();
+    assert_eq!(locations.last().copied(), almanac.chain.kth_location(&seed_ranges, 36));
+    assert_eq!(None, almanac.chain.kth_location(&seed_ranges, 37));
+}
+
+#[test]
+fn test_values_above_u32() {
+    // Real inputs have values past u32::MAX, which must not wrap on 32-bit targets.
+    let text = "seeds: 4294967290 10\n\nseed-to-location map:\n5000000000 4294967295 3\n";
+    let almanac = Almanac::parse(text).unwrap();
+
+    assert_eq!(vec![4_294_967_290, 10], almanac.seeds);
+    assert_eq!(Ok(5_000_000_001), almanac.chain.resolve(4_294_967_296, "location"));
+    assert_eq!(Ok(Some((4_294_967_290, 4_294_967_290))), almanac.lowest_seed_in_ranges());
+    assert_eq!(Some((10, 10)), almanac.lowest_seed());
+
+    // Values past u64::MAX do not fit and are rejected.
+    let error = Almanac::parse("seeds: 18446744073709551616 1\n").unwrap_err();
+    assert_eq!("line 1: invalid number `18446744073709551616`", error.to_string());
+}
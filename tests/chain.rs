@@ -0,0 +1,594 @@
+use aoc2023_day05::{to_dot, Almanac, Category, ChainBuilder, ChainError, RangeMap, RangeMapChain};
+
+fn example() -> Almanac {
+    Almanac::parse(include_str!("data/example.txt")).unwrap()
+}
+
+#[test]
+fn test_chain_from_library() {
+    let chain = RangeMapChain::new(vec![
+        (String::from("soil"), RangeMap::new(vec![(50, 98, 2), (52, 50, 48)])),
+        (String::from("fertilizer"), RangeMap::new(vec![(0, 15, 37), (37, 52, 2), (39, 0, 15)])),
+    ]).unwrap();
+
+    assert_eq!(Ok(81), chain.resolve(79, "soil"));
+    assert_eq!(Ok(35), chain.resolve(98, "fertilizer"));
+    assert_eq!(vec![(81, 14)], chain.resolve_ranges(&[(79, 14)], "soil").unwrap());
+    assert_eq!(vec![(35, 2)], chain.resolve_ranges(&[(98, 2)], "fertilizer").unwrap());
+}
+
+#[test]
+fn test_resolve_through_hole() {
+    let chain = RangeMapChain::new(vec![
+        (String::from("soil"), RangeMap::new(vec![(100, 0, 50)])),
+        (String::from("fertilizer"), RangeMap::new(vec![(500, 100, 10), (600, 130, 20)])),
+        (String::from("water"), RangeMap::new(vec![(0, 110, 20), (900, 500, 50)])),
+    ]).unwrap();
+
+    let brute_force = |seed: u64| {
+        chain.range_maps().iter().fold(seed, |value, (_, range_map)| {
+            range_map
+                .ranges()
+                .iter()
+                .find(|(_, source, size)| *source <= value && value < source + size)
+                .map_or(value, |(destination, source, _)| destination + value - source)
+        })
+    };
+
+    // 15 -> soil 115 -> fertilizer 115 (hole) -> water 5
+    assert_eq!(Ok(5), chain.resolve(15, "water"));
+    for seed in 0..200 {
+        assert_eq!(Ok(brute_force(seed)), chain.resolve(seed, "water"));
+    }
+    let error = chain.resolve(15, "lcoation").unwrap_err();
+    assert_eq!("unknown stage label 'lcoation', available: soil, fertilizer, water", error.to_string());
+    assert!(chain.resolve_ranges(&[(15, 1)], "lcoation").is_err());
+}
+
+#[test]
+fn test_compose_matches_resolve() {
+    for path in ["tests/data/example.txt", "input.txt"] {
+        let Almanac { seeds, chain } = Almanac::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let composed = chain.compose();
+
+        let values = chain
+            .range_maps()
+            .iter()
+            .flat_map(|(_, range_map)| range_map.ranges().iter())
+            .chain(composed.ranges().iter())
+            .flat_map(|&(destination, source, size)| [destination, source, source + size])
+            .chain(seeds)
+            .flat_map(|v| [v.saturating_sub(1), v, v + 1]);
+
+        for value in values {
+            assert_eq!(chain.resolve(value, "location"), Ok(composed.get_or_identity(value)), "seed {}", value);
+        }
+    }
+}
+
+#[test]
+fn test_resolve_reverse() {
+    let chain = RangeMapChain::new(vec![
+        (String::from("soil"), RangeMap::new(vec![(100, 0, 10), (100, 50, 10)])),
+        (String::from("fertilizer"), RangeMap::new(vec![(0, 100, 10), (200, 5, 5)])),
+    ]).unwrap();
+
+    let seeds = chain.resolve_reverse(3, "fertilizer").unwrap();
+    assert_eq!(vec![3, 53, 103], seeds);
+    for seed in seeds {
+        assert_eq!(Ok(3), chain.resolve(seed, "fertilizer"));
+    }
+
+    let chain = example().chain;
+    for location in [35, 46, 82, 86] {
+        let seeds = chain.resolve_reverse(location, "location").unwrap();
+        assert!(!seeds.is_empty());
+        for seed in seeds {
+            assert_eq!(Ok(location), chain.resolve(seed, "location"));
+        }
+    }
+    assert!(chain.resolve_reverse(35, "nowhere").is_err());
+}
+
+#[test]
+fn test_resolve_trace() {
+    let chain = example().chain;
+
+    let expected: Vec<(String, u64)> = [
+        ("soil", 81),
+        ("fertilizer", 81),
+        ("water", 81),
+        ("light", 74),
+        ("temperature", 78),
+        ("humidity", 78),
+        ("location", 82),
+    ]
+    .into_iter()
+    .map(|(label, value)| (String::from(label), value))
+    .collect();
+    assert_eq!(expected, chain.resolve_trace(79));
+}
+
+#[test]
+fn test_resolve_categories() {
+    let chain = example().chain;
+
+    for (category, label) in [(Category::Soil, "soil"), (Category::Location, "location")] {
+        assert_eq!(chain.resolve(79, label), chain.resolve_category(79, category));
+    }
+    assert_eq!(Ok(82), chain.resolve_category(79, Category::Location));
+    assert_eq!(Ok(78), chain.resolve_between_categories(81, Category::Water, Category::Temperature));
+    assert!(chain.resolve_between_categories(81, Category::Humidity, Category::Light).is_err());
+
+    let trace = chain.resolve_trace_categories(79).unwrap();
+    assert_eq!(Category::ALL[1..], trace.iter().map(|&(category, _)| category).collect::<Vec<_>>());
+    assert_eq!(Some(&(Category::Light, 74)), trace.get(3));
+    assert_eq!(Some(&(Category::Location, 82)), trace.last());
+
+    // Generic chains keep working with string labels, and only fail the typed methods.
+    let generic = ChainBuilder::new("seed")
+        .stage("soil", RangeMap::new(vec![(50, 98, 2)]))
+        .stage("sand", RangeMap::new(vec![(0, 50, 5)]))
+        .build()
+        .unwrap();
+    assert_eq!(Ok(1), generic.resolve(99, "sand"));
+    assert_eq!(Ok(51), generic.resolve_category(99, Category::Soil));
+    assert!(generic.resolve_category(99, Category::Location).is_err());
+    assert_eq!("sand", generic.resolve_trace_categories(99).unwrap_err().label);
+}
+
+#[test]
+fn test_resolve_ranges_stays_merged() {
+    // Every stage shuffles blocks of 10 around, splitting any range into many fragments that the
+    // next stage puts back together.
+    let shuffle = |stride: u64| {
+        RangeMap::new((0..100).map(|i| (((i * stride) % 100) * 10, i * 10, 10)).collect())
+    };
+    let chain = RangeMapChain::new(
+        ["a", "b", "c", "d"]
+            .into_iter()
+            .zip([7, 43, 61, 29])
+            .map(|(label, stride)| (String::from(label), shuffle(stride)))
+            .collect()
+    )
+    .unwrap();
+
+    let seeds = [(0, 1000), (5, 20), (995, 10)];
+    for label in ["a", "b", "c", "d"] {
+        assert_eq!(vec![(0, 1005)], chain.resolve_ranges(&seeds, label).unwrap());
+    }
+
+    let seeds = [(3, 250), (600, 75)];
+    let resolved = chain.resolve_ranges(&seeds, "d").unwrap();
+    assert!(resolved.len() <= 34);
+    let brute_force = seeds
+        .iter()
+        .flat_map(|&(start, size)| start..start + size)
+        .map(|seed| chain.resolve(seed, "d").unwrap())
+        .min();
+    assert_eq!(brute_force, resolved.iter().map(|&(start, _)| start).min());
+}
+
+#[test]
+fn test_min_location() {
+    let chain = example().chain;
+
+    assert_eq!(Some(46), chain.min_location(&[(79, 14), (55, 13)]));
+    assert_eq!(Some(35), chain.min_location_for_seeds(&[79, 14, 55, 13]));
+    assert_eq!(None, chain.min_location(&[]));
+    assert_eq!(None, chain.min_location_for_seeds(&[]));
+    assert_eq!(None, chain.min_location(&[(79, 0)]));
+    assert_eq!(Some(5), RangeMapChain::default().min_location(&[(5, 3)]));
+
+    let almanac = Almanac { seeds: Vec::new(), chain };
+    assert_eq!(None, almanac.lowest_location());
+    assert_eq!(Ok(None), almanac.lowest_location_for_ranges());
+    assert_eq!(Ok(None), almanac.lowest_seed_in_ranges());
+    assert_eq!(Ok(None), almanac.lowest_seed_in_ranges_boundary());
+}
+
+#[test]
+fn test_resolve_at() {
+    let chain = example().chain;
+
+    assert_eq!(7, chain.len());
+    assert_eq!(79, chain.resolve_at(79, 0));
+    assert_eq!(81, chain.resolve_at(79, 1));
+    assert_eq!(74, chain.resolve_at(79, 4));
+    for seed in 0..120 {
+        assert_eq!(Ok(chain.resolve_at(seed, chain.len())), chain.resolve(seed, "location"));
+        assert_eq!(chain.resolve_at(seed, chain.len()), chain.resolve_at(seed, 100));
+    }
+
+    assert_eq!(vec![(79, 14)], chain.resolve_ranges_at(&[(79, 14)], 0));
+    assert_eq!(chain.resolve_ranges(&[(79, 14)], "water").unwrap(), chain.resolve_ranges_at(&[(79, 14)], 3));
+    assert_eq!(chain.resolve_ranges(&[(79, 14)], "location").unwrap(), chain.resolve_ranges_at(&[(79, 14)], 8));
+}
+
+#[test]
+fn test_resolve_between() {
+    let chain = example().chain;
+
+    assert_eq!(Ok(78), chain.resolve_between(81, "water", "temperature"));
+    assert_eq!(Ok(82), chain.resolve_between(79, "seed", "location"));
+    assert_eq!(Ok(81), chain.resolve_between(81, "water", "water"));
+    assert_eq!(Ok(79), chain.resolve_between(79, "seed", "seed"));
+    assert_eq!(Ok(82), chain.resolve_between(82, "location", "location"));
+    assert_eq!(Ok(vec![(79, 14)]), chain.resolve_ranges_between(&[(79, 14)], "seed", "seed"));
+    assert_eq!(Ok(vec![(74, 14)]), chain.resolve_ranges_between(&[(81, 14)], "water", "light"));
+
+    assert_eq!(
+        "stage 'humidity' comes after 'light' in the chain",
+        chain.resolve_between(81, "humidity", "light").unwrap_err().to_string()
+    );
+    assert_eq!(
+        "unknown stage label 'wtaer', available: seed, soil, fertilizer, water, light, temperature, humidity, location",
+        chain.resolve_between(81, "wtaer", "light").unwrap_err().to_string()
+    );
+    assert!(chain.resolve_between(81, "water", "lihgt").is_err());
+}
+
+#[test]
+fn test_chain_validation() {
+    let stage = |label: &str| (String::from(label), RangeMap::default());
+
+    assert_eq!(
+        Err(ChainError::DuplicateLabel { index: 2, label: String::from("water") }),
+        RangeMapChain::new(vec![stage("water"), stage("soil"), stage("water")])
+    );
+    assert_eq!(Err(ChainError::EmptyLabel { index: 1 }), RangeMapChain::new(vec![stage("soil"), stage("")]));
+    assert_eq!(
+        Err(ChainError::Disconnected { index: 1, expected: String::from("soil"), found: String::from("water") }),
+        RangeMapChain::from_stages(vec![
+            (String::from("seed"), String::from("soil"), RangeMap::default()),
+            (String::from("water"), String::from("light"), RangeMap::default()),
+        ])
+    );
+    assert!(RangeMapChain::new(vec![stage("soil"), stage("water")]).is_ok());
+}
+
+#[test]
+fn test_lowest_locations() {
+    let chain = example().chain;
+
+    assert_eq!(vec![46, 47, 48], chain.lowest_locations(&[(79, 14), (55, 13)], 3));
+    assert_eq!(vec![35, 43, 82, 86], chain.lowest_locations_for_seeds(&[79, 14, 55, 13], 10));
+    assert_eq!(vec![35], chain.lowest_locations_for_seeds(&[13, 13, 13], 2));
+    assert_eq!(vec![5, 6, 7], RangeMapChain::default().lowest_locations(&[(5, 3)], 100));
+    assert!(chain.lowest_locations(&[(79, 14)], 0).is_empty());
+
+    let all: Vec<u64> = (79..93).chain(55..68).map(|seed| chain.resolve(seed, "location").unwrap()).collect();
+    let mut expected = all.clone();
+    expected.sort();
+    expected.dedup();
+    assert_eq!(expected, chain.lowest_locations(&[(79, 14), (55, 13)], all.len()));
+}
+
+#[test]
+fn test_preimage() {
+    let chain = example().chain;
+    let seed_ranges = [(79, 14), (55, 13)];
+    let seeds: Vec<u64> = seed_ranges.iter().flat_map(|&(start, size)| start..start + size).collect();
+
+    for target in [(0, 50), (46, 1), (50, 40), (60, 1), (86, 4), (0, 1000), (200, 10)] {
+        let preimage = chain.preimage(target, &seed_ranges);
+        let expected: Vec<u64> = seeds
+            .iter()
+            .copied()
+            .filter(|&seed| {
+                let location = chain.resolve(seed, "location").unwrap();
+                target.0 <= location && location < target.0 + target.1
+            })
+            .collect();
+        let mut found: Vec<u64> = preimage.iter().flat_map(|&(start, size)| start..start + size).collect();
+        found.sort();
+        let mut expected = expected;
+        expected.sort();
+        assert_eq!(expected, found, "{:?}", target);
+    }
+    assert_eq!(vec![(82, 10)], chain.preimage((46, 10), &seed_ranges));
+    assert_eq!(vec![(0, 1000)], RangeMapChain::default().preimage((0, 1000), &[(0, 1000)]));
+}
+
+#[test]
+fn test_chain_builder() {
+    let seed_to_soil = RangeMap::new(vec![(50, 98, 2), (52, 50, 48)]);
+    let soil_to_fertilizer = RangeMap::new(vec![(0, 15, 37), (37, 52, 2), (39, 0, 15)]);
+
+    let chain = ChainBuilder::new("seed")
+        .stage("soil", seed_to_soil.clone())
+        .stage("fertilizer", soil_to_fertilizer.clone())
+        .build()
+        .unwrap();
+    assert_eq!(
+        RangeMapChain::new(vec![
+            (String::from("soil"), seed_to_soil.clone()),
+            (String::from("fertilizer"), soil_to_fertilizer.clone()),
+        ]),
+        Ok(chain.clone())
+    );
+    assert_eq!(Some("soil"), chain.source(1));
+
+    let chain = ChainBuilder::new("seed")
+        .stage_from("seed", "soil", seed_to_soil.clone())
+        .stage_from("soil", "fertilizer", soil_to_fertilizer.clone())
+        .build();
+    assert!(chain.is_ok());
+
+    assert_eq!(
+        Err(ChainError::Disconnected { index: 1, expected: String::from("soil"), found: String::from("water") }),
+        ChainBuilder::new("seed")
+            .stage("soil", seed_to_soil.clone())
+            .stage_from("water", "light", RangeMap::default())
+            .build()
+    );
+    assert_eq!(
+        Err(ChainError::Disconnected { index: 0, expected: String::from("seed"), found: String::from("soil") }),
+        ChainBuilder::new("seed").stage_from("soil", "fertilizer", soil_to_fertilizer).build()
+    );
+    assert_eq!(
+        Err(ChainError::EmptyLabel { index: 1 }),
+        ChainBuilder::new("seed").stage("soil", seed_to_soil).stage("", RangeMap::default()).build()
+    );
+    assert_eq!(Ok(RangeMapChain::default()), ChainBuilder::new("seed").build());
+}
+
+#[test]
+fn test_stage_ids() {
+    let chain = example().chain;
+    let water = chain.stage_id("water").unwrap();
+    let location = chain.stage_id("location").unwrap();
+
+    assert_eq!(None, chain.stage_id("seed"));
+    assert!(water < location);
+    for seed in 0..120 {
+        assert_eq!(Ok(chain.resolve_to(seed, water)), chain.resolve(seed, "water"));
+        assert_eq!(Ok(chain.resolve_to(seed, location)), chain.resolve(seed, "location"));
+    }
+    assert_eq!(chain.resolve_ranges(&[(79, 14)], "water").unwrap(), chain.resolve_ranges_to(&[(79, 14)], water));
+}
+
+#[test]
+fn test_resolve_many() {
+    let almanac = example();
+    let values = [79, 14, 55, 13, 0, 99, 14];
+
+    assert_eq!(Ok(vec![82, 43, 86, 35, 22, 19, 43]), almanac.chain.resolve_many(&values, "location"));
+    assert_eq!(Ok(vec![81, 14, 57, 13, 0, 51, 14]), almanac.chain.resolve_many(&values, "soil"));
+    assert_eq!(Ok(vec![]), almanac.chain.resolve_many(&[], "location"));
+    assert!(almanac.chain.resolve_many(&values, "seed").is_err());
+}
+
+#[test]
+fn test_count_locations() {
+    let chain = example().chain;
+    assert_eq!(27, chain.count_locations(&[(79, 14), (55, 13)]));
+    assert_eq!(27, chain.count_locations(&[(79, 14), (55, 13), (80, 5)]));
+    assert_eq!(0, chain.count_locations(&[]));
+
+    // Seeds 0..10 and 20..30 land on overlapping locations.
+    let overlapping = Almanac::parse(include_str!("data/non_injective.txt")).unwrap();
+    assert_eq!(25, overlapping.chain.count_locations(&overlapping.seed_ranges().unwrap()));
+    assert_eq!(u64::MAX, RangeMapChain::default().count_locations(&[(0, u64::MAX)]));
+}
+
+#[test]
+fn test_location_histogram() {
+    let almanac = example();
+    let seed_ranges = almanac.seed_ranges().unwrap();
+    let seeds: Vec<u64> = seed_ranges.iter().flat_map(|&(start, size)| start..start + size).collect();
+    for bucket_size in [1, 3, 10, 1000] {
+        let mut brute_force: Vec<(u64, u64)> = Vec::new();
+        let mut locations = almanac.chain.resolve_many(&seeds, "location").unwrap();
+        locations.sort_unstable();
+        for location in locations {
+            let bucket = location / bucket_size * bucket_size;
+            match brute_force.last_mut() {
+                Some((last, count)) if *last == bucket => *count += 1,
+                _ => brute_force.push((bucket, 1)),
+            }
+        }
+        assert_eq!(brute_force, almanac.chain.location_histogram(&seed_ranges, bucket_size), "{}", bucket_size);
+    }
+    assert_eq!(
+        vec![(40, 4), (50, 10), (60, 1), (80, 7), (90, 5)],
+        almanac.chain.location_histogram(&seed_ranges, 10)
+    );
+
+    // Both halves of the seeds land on 105..110.
+    let overlapping = Almanac::parse(include_str!("data/non_injective.txt")).unwrap();
+    assert_eq!(
+        vec![(10, 10), (100, 15), (110, 5)],
+        overlapping.chain.location_histogram(&overlapping.seed_ranges().unwrap(), 10)
+    );
+    let identity = RangeMapChain::default();
+    assert_eq!(vec![(0, 1), (u64::MAX - 1, 1)], identity.location_histogram(&[(u64::MAX - 2, 2)], u64::MAX - 1));
+}
+
+#[test]
+fn test_kth_location() {
+    let chain = example().chain;
+    let overlapping = Almanac::parse(include_str!("data/non_injective.txt")).unwrap();
+    let cases = [
+        (&chain, vec![(79, 14), (55, 13)]),
+        (&chain, vec![(0, 100), (90, 20)]),
+        (&overlapping.chain, overlapping.seed_ranges().unwrap()),
+    ];
+    for (chain, seed_ranges) in cases {
+        let mut seeds: Vec<u64> = seed_ranges.iter().flat_map(|&(start, size)| start..start + size).collect();
+        seeds.sort_unstable();
+        seeds.dedup();
+        let mut locations = chain.resolve_many(&seeds, "location").unwrap();
+        locations.sort_unstable();
+        let mut distinct = locations.clone();
+        distinct.dedup();
+
+        assert_eq!(None, chain.kth_location(&seed_ranges, 0));
+        assert_eq!(None, chain.kth_distinct_location(&seed_ranges, 0));
+        for k in 1..=locations.len() + 1 {
+            let kth = k as u64;
+            assert_eq!(locations.get(k - 1).copied(), chain.kth_location(&seed_ranges, kth), "{:?} {}", seed_ranges, k);
+            assert_eq!(distinct.get(k - 1).copied(), chain.kth_distinct_location(&seed_ranges, kth));
+        }
+    }
+
+    let chain = example().chain;
+    assert_eq!(chain.min_location(&[(79, 14), (55, 13)]), chain.kth_location(&[(79, 14), (55, 13)], 1));
+    assert_eq!(None, chain.kth_location(&[], 1));
+    // A billion seeds through the identity.
+    let identity = RangeMapChain::default();
+    assert_eq!(Some(3_999_999_999), identity.kth_location(&[(3_000_000_000, 1_000_000_000)], 1_000_000_000));
+}
+
+#[test]
+fn test_composed_cache() {
+    let mut chain = example().chain;
+    let location = chain.last_stage().unwrap();
+    let untouched = chain.clone();
+    assert_eq!(Ok(82), chain.resolve(79, "location"));
+    assert_eq!(untouched, chain);
+
+    // Seed 79 goes through humidity 78, now moved by the new rule.
+    chain.range_map_mut(location).unwrap().push((1000, 78, 1));
+    assert_eq!(Ok(1000), chain.resolve(79, "location"));
+    assert_eq!(1000, chain.resolve_to(79, location));
+    assert_eq!(Ok(vec![1000, 43]), chain.resolve_many(&[79, 14], "location"));
+    assert_eq!(1000, chain.resolve_at(79, chain.len()));
+    assert_ne!(untouched, chain);
+
+    let temperature = chain.stage_id("temperature").unwrap();
+    chain.truncate(temperature);
+    assert_eq!(Ok(78), chain.resolve(79, "temperature"));
+    assert_eq!(78, chain.resolve_to(79, location));
+    assert!(chain.range_map_mut(location).is_none());
+
+    // Overlapping rules are never composed, so lookups keep the overlap policy of `get`.
+    let overlapping = Almanac::parse(include_str!("data/overlapping.txt")).unwrap().chain;
+    let last = overlapping.range_maps().last().unwrap().0.clone();
+    for value in 0..200 {
+        assert_eq!(overlapping.resolve_at(value, overlapping.len()), overlapping.resolve(value, &last).unwrap());
+    }
+}
+
+#[test]
+fn test_truncate() {
+    let mut almanac = example();
+    let full = almanac.chain.clone();
+    assert_eq!(full.stage_id("location"), full.last_stage());
+    assert_eq!(None, RangeMapChain::default().last_stage());
+
+    let temperature = full.stage_id("temperature").unwrap();
+    almanac.chain.truncate(temperature);
+    assert_eq!(5, almanac.chain.len());
+    assert_eq!(Some(temperature), almanac.chain.last_stage());
+    assert_eq!(Some("light"), almanac.chain.source(4));
+    assert_eq!(None, almanac.chain.stage_id("humidity"));
+    for seed in 0..120 {
+        assert_eq!(full.resolve(seed, "temperature"), almanac.chain.resolve(seed, "temperature"));
+    }
+
+    // The lowest location is now the lowest temperature, whichever way it is found.
+    assert_eq!(Some((13, 34)), almanac.lowest_seed());
+    assert_eq!(Ok(Some((82, 45))), almanac.lowest_seed_in_ranges());
+    assert_eq!(almanac.lowest_seed_in_ranges(), almanac.lowest_seed_in_ranges_boundary());
+    assert_eq!(almanac.lowest_seed_in_ranges(), almanac.lowest_seed_in_ranges_brute_force());
+    assert_eq!(Ok(Some(45)), almanac.verify(None));
+
+    almanac.chain.truncate(full.last_stage().unwrap());
+    assert_eq!(5, almanac.chain.len());
+}
+
+#[test]
+fn test_min_location_boundary() {
+    for path in ["tests/data/example.txt", "input.txt"] {
+        let almanac = Almanac::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let seed_ranges = almanac.seed_ranges().unwrap();
+        let boundary = almanac.chain.min_location_boundary(&seed_ranges);
+        assert_eq!(almanac.chain.min_location(&seed_ranges), boundary, "{}", path);
+    }
+
+    let chain = example().chain;
+    for seed_ranges in [vec![(0, 100)], vec![(79, 1)], vec![(90, 3), (10, 5)], vec![(40, 20), (97, 4)]] {
+        let brute_force = seed_ranges
+            .iter()
+            .flat_map(|&(start, size)| start..start + size)
+            .map(|seed| chain.resolve(seed, "location").unwrap())
+            .min();
+        assert_eq!(brute_force, chain.min_location_boundary(&seed_ranges), "{:?}", seed_ranges);
+        assert_eq!(chain.min_location(&seed_ranges), chain.min_location_boundary(&seed_ranges));
+    }
+    assert_eq!(None, chain.min_location_boundary(&[]));
+    assert_eq!(None, chain.min_location_boundary(&[(5, 0)]));
+}
+
+/// Splits the statements of a DOT digraph into nodes and `(from, to, label)` edges, unquoting
+/// identifiers, and checks the structure around them.
+fn parse_dot(dot: &str) -> (Vec<String>, Vec<(String, String, String)>) {
+    let body = dot.strip_prefix("digraph almanac {\n").and_then(|body| body.strip_suffix("}\n")).unwrap();
+    let mut tokens = Vec::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => token.push(chars.next().unwrap()),
+                        '"' => break,
+                        c => token.push(c),
+                    }
+                }
+                tokens.push(token);
+            }
+            '-' if chars.next_if_eq(&'>').is_some() => tokens.push(String::from("->")),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = String::from(c);
+                while let Some(c) = chars.next_if(|next| c.is_alphanumeric() && next.is_alphanumeric()) {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    let (mut nodes, mut edges) = (Vec::new(), Vec::new());
+    for statement in tokens.split(|token| token == ";").filter(|statement| !statement.is_empty()) {
+        match statement {
+            [node] => nodes.push(node.clone()),
+            [from, arrow, to, open, key, equals, label, close]
+                if (arrow.as_str(), open.as_str(), key.as_str(), equals.as_str(), close.as_str())
+                    == ("->", "[", "label", "=", "]") =>
+            {
+                edges.push((from.clone(), to.clone(), label.clone()))
+            }
+            _ => panic!("unexpected statement {:?}", statement),
+        }
+    }
+    (nodes, edges)
+}
+
+#[test]
+fn test_to_dot() {
+    let chain = example().chain;
+    let dot = to_dot(&chain);
+    assert_eq!(dot, to_dot(&chain.clone()));
+
+    let (nodes, edges) = parse_dot(&dot);
+    assert_eq!(Category::ALL.map(|category| category.to_string()).to_vec(), nodes);
+    assert_eq!(7, edges.len());
+    assert_eq!((String::from("seed"), String::from("soil"), String::from("2 rules, 50 covered")), edges[0]);
+    assert_eq!(
+        (String::from("humidity"), String::from("location"), String::from("2 rules, 41 covered")),
+        edges[6]
+    );
+
+    let chain = ChainBuilder::new("seed")
+        .stage("the \"soil\"", RangeMap::new(vec![(0, 10, 5), (100, 12, 5)]))
+        .stage("back\\slash", RangeMap::new(vec![(0, 0, 1)]))
+        .build()
+        .unwrap();
+    let (nodes, edges) = parse_dot(&to_dot(&chain));
+    assert_eq!(vec!["seed", "the \"soil\"", "back\\slash"], nodes);
+    assert_eq!("2 rules, 7 covered", edges[0].2);
+    assert_eq!("1 rule, 1 covered", edges[1].2);
+}
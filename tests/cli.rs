@@ -0,0 +1,771 @@
+use std::process::{Command, Output};
+
+use aoc2023_day05::Almanac;
+
+/// The binary, with the input variable cleared so that the environment of the tests does not leak
+/// into them.
+fn command() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_aoc2023-day05"));
+    command.env_remove("AOC_DAY05_INPUT");
+    command
+}
+
+fn run(args: &[&str]) -> Output {
+    command().args(args).output().unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = run(args);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_part_flag() {
+    assert_eq!(
+        "Minimum location for seeds: 35\nReached from seed 13\n",
+        stdout(&["--part", "1", "tests/data/example.txt"])
+    );
+    assert_eq!(
+        "Minimum location for seeds: 46\nReached from seed 82 (input range starting at 79)\n",
+        stdout(&["--part=2", "tests/data/example.txt"])
+    );
+    assert_eq!(stdout(&["--part=2", "tests/data/example.txt"]), stdout(&["tests/data/example.txt"]));
+    assert_eq!(Some(2), run(&["--part", "3", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_trace_flag() {
+    assert_eq!(
+        "seed 79 -> soil 81 -> fertilizer 81 -> water 81 -> light 74 -> temperature 78 -> humidity 78 -> location 82\n\
+         seed 55 -> soil 57 -> fertilizer 57 -> water 53 -> light 46 -> temperature 82 -> humidity 82 -> location 86\n",
+        stdout(&["--trace", "tests/data/example.txt"])
+    );
+    assert_eq!(4, stdout(&["--trace", "--part", "1", "tests/data/example.txt"]).lines().count());
+    assert_eq!(
+        "seed 13 -> soil 13 -> fertilizer 52 -> water 41 -> light 34 -> temperature 34 -> humidity 35 -> location 35\n",
+        stdout(&["--trace=13", "tests/data/example.txt"])
+    );
+}
+
+#[test]
+fn test_json_output() {
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&["--output", "json", "--part", "1", "tests/data/example.txt"])).unwrap();
+    assert_eq!(1, report["part"]);
+    assert_eq!(35, report["minimum_location"]);
+    assert_eq!(13, report["seed"]);
+    assert!(report["elapsed_us"].is_u64());
+
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&["--output=json", "tests/data/example.txt"])).unwrap();
+    assert_eq!(2, report["part"]);
+    assert_eq!(46, report["minimum_location"]);
+    assert_eq!(82, report["seed"]);
+
+    assert_eq!(Some(2), run(&["--output", "yaml", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_brute_force_flag() {
+    assert_eq!(
+        stdout(&["tests/data/example.txt"]),
+        stdout(&["--brute-force", "--verify", "tests/data/example.txt"])
+    );
+    assert_eq!(Some(2), run(&["--sample", "3", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_verify_flag() {
+    let expected = stdout(&["tests/data/example.txt"]);
+    assert_eq!(expected, stdout(&["--verify", "tests/data/example.txt"]));
+    assert_eq!(expected, stdout(&["--verify", "--sample", "2", "tests/data/example.txt"]));
+    assert_eq!(Some(2), run(&["--verify", "--sample", "0", "tests/data/example.txt"]).status.code());
+    assert_fails(&["--verify", "--part", "1", "tests/data/example.txt"], 2, "error: --verify requires --part 2");
+}
+
+#[test]
+fn test_brute_force_progress() {
+    let output = run(&["--brute-force", "tests/data/example.txt"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("\rprogress: "), "{}", stderr);
+    assert!(stderr.ends_with("\rprogress: 100.0% of 27 seeds, ETA 0s, best location so far 46\n"), "{}", stderr);
+    assert_eq!(stdout(&["tests/data/example.txt"]), String::from_utf8(output.stdout).unwrap());
+
+    let output = run(&["--brute-force", "--quiet", "tests/data/example.txt"]);
+    assert!(output.stderr.is_empty());
+    assert_eq!(stdout(&["tests/data/example.txt"]), String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn test_algorithm_flag() {
+    let expected = stdout(&["tests/data/example.txt"]);
+    for algorithm in ["ranges", "boundary", "brute-force"] {
+        assert_eq!(expected, stdout(&["--algorithm", algorithm, "tests/data/example.txt"]), "{}", algorithm);
+    }
+    assert_eq!(expected, stdout(&["--algorithm=boundary", "--verify", "tests/data/example.txt"]));
+    assert_eq!(Some(2), run(&["--algorithm", "fast", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_odd_seed_count() {
+    let output = run(&["tests/data/odd_seeds.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "error: part 2 requires an even number of seed values, found 3\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout(&["--part", "1", "tests/data/odd_seeds.txt"]).starts_with("Minimum location for seeds: 11\n"));
+}
+
+#[test]
+fn test_overlapping_rules() {
+    let output = run(&["tests/data/overlapping.txt"]);
+    assert!(output.status.success());
+    assert_eq!(
+        "warning: map `soil` has rules 0 and 1 overlapping over 3..5\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run(&["--strict", "tests/data/overlapping.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "error: line 3: 1 pair(s) of rules with overlapping source ranges, first at 3..5\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_seed_override() {
+    assert_eq!(
+        "Minimum location for seeds: 43\nReached from seed 14\n",
+        stdout(&["--part", "1", "--seed", "79", "--seed=14", "tests/data/example.txt"])
+    );
+    assert_eq!(
+        "Minimum location for seeds: 82\nReached from seed 79 (input range starting at 79)\n",
+        stdout(&["--seed-range", "79:1", "tests/data/example.txt"])
+    );
+    assert_eq!(
+        "seed 14 -> soil 14 -> fertilizer 53 -> water 49 -> light 42 -> temperature 42 -> humidity 43 -> location 43\n",
+        stdout(&["--seed-range=14:3", "--trace", "tests/data/example.txt"])
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&["--seed-range", "55:13", "--output", "json", "tests/data/example.txt"])).unwrap();
+    assert_eq!(56, report["minimum_location"]);
+
+    assert_eq!(Some(2), run(&["--seed", "79", "tests/data/example.txt"]).status.code());
+    assert_eq!(Some(2), run(&["--part", "1", "--seed-range", "79:1", "tests/data/example.txt"]).status.code());
+    assert_eq!(Some(2), run(&["--seed-range", "79", "tests/data/example.txt"]).status.code());
+    assert_fails(
+        &["--seed-range", "18446744073709551610:10", "tests/data/example.txt"],
+        2,
+        "error: invalid range `18446744073709551610:10` for --seed-range, START + LEN must fit in 64 bits\n",
+    );
+    assert_eq!(Some(2), run(&["--part", "1", "--seed", "x", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_reverse_flag() {
+    assert_eq!("seed 82 -> location 46\n", stdout(&["--reverse", "46", "tests/data/example.txt"]));
+    assert_eq!("seed 13 -> location 35\n", stdout(&["--part", "1", "--reverse=35", "tests/data/example.txt"]));
+    assert_eq!("No seeds reach location 35\n", stdout(&["--reverse", "35", "tests/data/example.txt"]));
+}
+
+#[test]
+fn test_gzip_input() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&std::fs::read("tests/data/example.txt").unwrap()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let path = format!("{}/example.txt.gz", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, &compressed).unwrap();
+    assert_eq!(stdout(&["tests/data/example.txt"]), stdout(&[&path]));
+
+    let mut child = command()
+        .args(["--gzip", "--part", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&compressed).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!("Minimum location for seeds: 35\nReached from seed 13\n", String::from_utf8(output.stdout).unwrap());
+
+    let path = format!("{}/corrupt.txt.gz", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, b"seeds: 79 14 55 13\n").unwrap();
+    let output = run(&[&path]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: line 1: "));
+}
+
+#[test]
+fn test_json_input() {
+    for part in ["1", "2"] {
+        assert_eq!(
+            stdout(&["--part", part, "tests/data/example.txt"]),
+            stdout(&["--part", part, "tests/data/example.json"])
+        );
+    }
+    assert_eq!(stdout(&["tests/data/example.json"]), stdout(&["--input-format", "json", "tests/data/example.json"]));
+
+    let output = run(&["--input-format=text", "tests/data/example.json"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(Some(2), run(&["--strict", "--input-format=json", "tests/data/example.json"]).status.code());
+}
+
+#[test]
+fn test_validate_flag() {
+    assert_eq!("ok: 7 maps, 18 rules, 4 seeds\n", stdout(&["--validate", "tests/data/example.txt"]));
+
+    let output = run(&["--validate", "tests/data/overlapping.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "chain ends at `soil`, expected `location`\nmap `soil`: rules `10 0 5` and `20 3 5` overlap over 3..5\n\
+         2 problem(s) found\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let output = run(&["--validate", "tests/data/odd_seeds.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("seeds: part 2 requires an even number"));
+    assert!(run(&["--validate", "--part", "1", "tests/data/odd_seeds.txt"]).status.success());
+
+    assert_eq!(
+        "warning: map `location` is not injective, rules `100 0 10` and `105 20 10` both map onto 105..110\n\
+         ok: 1 maps, 2 rules, 2 seeds\n",
+        stdout(&["--validate", "tests/data/non_injective.txt"])
+    );
+}
+
+#[test]
+fn test_top_flag() {
+    assert_eq!("Lowest 3 locations for seeds: 46, 47, 48\n", stdout(&["--top", "3", "tests/data/example.txt"]));
+    assert_eq!(
+        "Lowest 4 locations for seeds: 35, 43, 82, 86\n",
+        stdout(&["--top=10", "--part", "1", "tests/data/example.txt"])
+    );
+    assert_eq!(
+        "{\"part\":2,\"locations\":[46,47]}\n",
+        stdout(&["--top", "2", "--output", "json", "tests/data/example.txt"])
+    );
+    assert_eq!(Some(2), run(&["--top", "0", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_csv_output() {
+    let almanac = aoc2023_day05::Almanac::parse(include_str!("data/example.txt")).unwrap();
+
+    let csv = stdout(&["--output", "csv", "--part", "1", "tests/data/example.txt"]);
+    let mut rows = csv.lines().map(|line| line.split(',').collect::<Vec<&str>>());
+    let header = rows.next().unwrap();
+    assert_eq!(vec!["seed", "soil", "fertilizer", "water", "light", "temperature", "humidity", "location"], header);
+    for (row, &seed) in rows.zip(&almanac.seeds) {
+        let trace = almanac.chain.resolve_trace(seed);
+        assert_eq!(seed.to_string(), row[0]);
+        for ((label, value), (column, cell)) in trace.iter().zip(header[1..].iter().zip(&row[1..])) {
+            assert_eq!((label.as_str(), value.to_string()), (*column, String::from(*cell)));
+        }
+    }
+
+    let csv = stdout(&["--output=csv", "tests/data/example.txt"]);
+    let mut rows = csv.lines();
+    assert_eq!(Some("seed_start,seed_len,location_start,location_len"), rows.next());
+    let mut covered = 0;
+    for row in rows {
+        let row: Vec<u64> = row.split(',').map(|cell| cell.parse().unwrap()).collect();
+        assert_eq!(row[1], row[3]);
+        covered += row[1];
+        for offset in [0, row[1] - 1] {
+            let trace = almanac.chain.resolve_trace(row[0] + offset);
+            assert_eq!(Some(&(String::from("location"), row[2] + offset)), trace.last());
+        }
+    }
+    assert_eq!(27, covered);
+}
+
+#[test]
+fn test_errors_do_not_panic() {
+    let cases: [(&[&str], i32, &str); 6] = [
+        (&["inupt.txt"], 1, "error: could not open 'inupt.txt': "),
+        (&["tests/data"], 1, "error: line 1: could not read line: "),
+        (&["--part"], 2, "error: missing value for --part\nusage: "),
+        (&["--seed", "x", "--part", "1"], 2, "error: invalid number `x` for --seed\nusage: "),
+        (&["tests/data/example.txt", "--extra"], 2, "error: unknown flag `--extra`\nusage: "),
+        (&["--seed-range", "5:0", "tests/data/example.txt"], 1, "error: no seeds to map\n"),
+    ];
+    for (args, code, message) in cases {
+        let output = run(args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(Some(code), output.status.code(), "{:?}: {}", args, stderr);
+        assert!(stderr.starts_with(message), "{:?}: {}", args, stderr);
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn test_explain_flag() {
+    assert_eq!(
+        "seed 79:\n  \
+         seed-to-soil: rule #1 (52 50 48) -> 81\n  \
+         soil-to-fertilizer: no rule, identity -> 81\n  \
+         fertilizer-to-water: no rule, identity -> 81\n  \
+         water-to-light: rule #2 (18 25 70) -> 74\n  \
+         light-to-temperature: rule #2 (68 64 13) -> 78\n  \
+         temperature-to-humidity: no rule, identity -> 78\n  \
+         humidity-to-location: rule #1 (60 56 37) -> 82\n",
+        stdout(&["--explain", "79", "tests/data/example.txt"])
+    );
+
+    let explained = stdout(&["--explain=79,14", "--explain", "55", "tests/data/example.txt"]);
+    let blocks: Vec<&str> = explained.split("\n\n").collect();
+    assert_eq!(3, blocks.len());
+    assert!(blocks[1].starts_with("seed 14:\n") && blocks[1].ends_with("-> 43"));
+    assert!(blocks[2].starts_with("seed 55:\n") && blocks[2].ends_with("-> 86\n"));
+    assert_eq!(Some(2), run(&["--explain", "7x", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_several_inputs() {
+    assert_eq!(
+        "tests/data/example.txt: 46\ntests/data/example.json: 46\n",
+        stdout(&["tests/data/example.txt", "tests/data/example.json"])
+    );
+    assert_eq!(
+        "tests/data/odd_seeds.txt: 11\ntests/data/example.txt: 35\n",
+        stdout(&["--part", "1", "tests/data/odd_seeds.txt", "tests/data/example.txt"])
+    );
+
+    let output = run(&["tests/data/example.txt", "tests/data/missing.txt", "tests/data/example.json"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "tests/data/example.txt: 46\ntests/data/example.json: 46\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error: tests/data/missing.txt: could not open"));
+
+    let output = run(&["--output", "json", "tests/data/odd_seeds.txt", "tests/data/example.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!("tests/data/odd_seeds.txt", reports[0]["path"]);
+    assert_eq!("part 2 requires an even number of seed values, found 3", reports[0]["error"]);
+    assert_eq!("tests/data/example.txt", reports[1]["path"]);
+    assert_eq!(46, reports[1]["minimum_location"]);
+    assert_eq!(82, reports[1]["seed"]);
+    assert!(reports[1].get("error").is_none());
+
+    assert_eq!(Some(2), run(&["--validate", "tests/data/example.txt", "tests/data/example.json"]).status.code());
+}
+
+#[test]
+fn test_help_flag() {
+    let help = stdout(&["--help"]);
+    assert!(help.starts_with("usage: aoc2023-day05 "));
+    for flag in ["--part", "--seed-range", "--explain", "--top", "--algorithm", "--verify", "--input-format", "-h, --help"] {
+        assert!(help.contains(&format!("\n  {}", flag)), "{} is not listed", flag);
+    }
+    assert_eq!(help, stdout(&["-h"]));
+    // Help wins over anything that would fail.
+    assert_eq!(help, stdout(&["--part", "1", "--seed-range", "1:2", "--help"]));
+
+    for flag in ["--prat", "--prat=1", "-x"] {
+        let output = run(&[flag, "tests/data/example.txt"]);
+        assert_eq!(Some(2), output.status.code());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let name = flag.split('=').next().unwrap();
+        assert!(stderr.starts_with(&format!("error: unknown flag `{}`\nusage: ", name)), "{}", stderr);
+    }
+}
+
+/// Runs `args`, expecting it to fail with `status` and an error starting with `error`.
+fn assert_fails(args: &[&str], status: i32, error: &str) {
+    let output = run(args);
+    assert_eq!(Some(status), output.status.code(), "{:?}", args);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(error), "{:?}: {}", args, stderr);
+}
+
+#[test]
+fn test_solve_subcommand() {
+    let solved = stdout(&["--part", "1", "tests/data/example.txt"]);
+    assert_eq!(solved, stdout(&["solve", "--part", "1", "tests/data/example.txt"]));
+    assert_eq!(stdout(&["--help"]), stdout(&["solve", "--help"]));
+    let error = "error: invalid part `3`, expected 1 or 2\nusage: ";
+    assert_fails(&["solve", "--part", "3", "tests/data/example.txt"], 2, error);
+}
+
+#[test]
+fn test_trace_subcommand() {
+    assert_eq!(
+        "seed 79 -> soil 81 -> fertilizer 81 -> water 81 -> light 74 -> temperature 78 -> humidity 78 -> location 82\n\
+         seed 13 -> soil 13 -> fertilizer 52 -> water 41 -> light 34 -> temperature 34 -> humidity 35 -> location 35\n",
+        stdout(&["trace", "tests/data/example.txt", "79", "13"])
+    );
+    assert_eq!(
+        stdout(&["--trace", "--part", "1", "tests/data/example.txt"]),
+        stdout(&["trace", "--part", "1", "tests/data/example.txt"])
+    );
+    assert_eq!("seed 79 -> soil 81\n", stdout(&["trace", "--stage", "soil", "tests/data/example.txt", "79"]));
+
+    assert_fails(&["trace", "tests/data/example.txt", "x"], 2, "error: invalid seed `x`\nusage: aoc2023-day05 trace ");
+    assert_fails(&["trace", "--top", "2", "tests/data/example.txt"], 2, "error: `trace` does not take --top\n");
+    // Without an input, the empty stdin of the test is read.
+    assert_fails(&["trace"], 1, "error: line 1: unexpected end of input");
+}
+
+#[test]
+fn test_reverse_subcommand() {
+    assert_eq!("seed 82 -> location 46\n", stdout(&["reverse", "tests/data/example.txt", "46"]));
+    assert_eq!("No seeds reach location 0\n", stdout(&["reverse", "--part", "1", "tests/data/example.txt", "0"]));
+
+    assert_fails(&["reverse", "tests/data/example.txt"], 2, "error: `reverse` takes an input and a location\n");
+    assert_fails(&["reverse", "tests/data/example.txt", "4x"], 2, "error: invalid number `4x` for reverse\n");
+}
+
+#[test]
+fn test_compose_subcommand() {
+    let composed = stdout(&["compose", "tests/data/example.txt"]);
+    assert!(composed.starts_with("seeds: 79 14 55 13\n\nseed-to-location map:\n"), "{}", composed);
+    let path = format!("{}/compose.txt", env!("CARGO_TARGET_TMPDIR"));
+    assert_eq!("", stdout(&["compose", "tests/data/example.txt", "-o", &path]));
+    assert_eq!(composed, std::fs::read_to_string(&path).unwrap());
+    assert_eq!(stdout(&["tests/data/example.txt"]), stdout(&[&path]));
+    let total = stdout(&["compose", "--include-identity", "tests/data/example.txt"]);
+    assert!(total.ends_with("\n100 100 18446744073709551515\n"), "{}", total);
+
+    let error = "error: `compose` takes a single input, found `extra` after it\n";
+    assert_fails(&["compose", "tests/data/example.txt", "extra"], 2, error);
+    assert_fails(&["-o", &path, "tests/data/example.txt"], 2, "error: unknown flag `-o`\n");
+}
+
+#[test]
+fn test_validate_subcommand() {
+    assert_eq!("ok: 7 maps, 18 rules, 4 seeds\n", stdout(&["validate", "tests/data/example.txt"]));
+
+    let output = run(&["validate", "tests/data/overlapping.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with(" problem(s) found\n"));
+    let error = "error: `validate` does not take --stage\n";
+    assert_fails(&["validate", "--stage", "soil", "tests/data/example.txt"], 2, error);
+}
+
+#[test]
+fn test_stats_subcommand() {
+    let stats = stdout(&["stats", "tests/data/example.txt"]);
+    let lines: Vec<&str> = stats.lines().collect();
+    assert_eq!(9, lines.len());
+    assert_eq!("seeds: 4 values, 27 seeds as ranges", lines[0]);
+    assert_eq!("seed-to-soil map: 2 rule(s) covering 50 values, sources 50 to 99", lines[1]);
+    assert_eq!("total: 7 map(s), 18 rule(s)", lines[8]);
+    assert!(stdout(&["stats", "tests/data/odd_seeds.txt"]).starts_with("seeds: 3 values, an odd count "));
+
+    assert_fails(&["stats", "tests/data/missing.txt"], 1, "error: could not open 'tests/data/missing.txt': ");
+    let help = stdout(&["stats", "--help"]);
+    assert!(help.starts_with("usage: aoc2023-day05 stats "));
+    assert!(help.contains("\n  --input-format text|json "));
+}
+
+#[test]
+fn test_input_variable() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let expected = stdout(&["--part", "1", "tests/data/example.txt"]);
+    let with_variable =
+        |value: &str, args: &[&str]| command().env("AOC_DAY05_INPUT", value).args(args).output().unwrap();
+
+    let output = with_variable("tests/data/example.txt", &["--part", "1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(expected, String::from_utf8(output.stdout).unwrap());
+    let output = with_variable("tests/data/example.txt", &["stats"]);
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("seeds: 4 values, "));
+
+    // A path on the command line wins over the variable.
+    let output = with_variable("tests/data/missing.txt", &["--part", "1", "tests/data/example.txt"]);
+    assert_eq!(expected, String::from_utf8(output.stdout).unwrap());
+    let output = with_variable("tests/data/missing.txt", &["--part", "1"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("error: could not open 'tests/data/missing.txt': "));
+
+    // Unset or empty, stdin is read since it is not a terminal.
+    for value in [None, Some("")] {
+        let mut command = command();
+        if let Some(value) = value {
+            command.env("AOC_DAY05_INPUT", value);
+        }
+        let mut child = command.args(["--part", "1"]).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(&std::fs::read("tests/data/example.txt").unwrap()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(expected, String::from_utf8(output.stdout).unwrap());
+    }
+
+    let help = stdout(&["--help"]);
+    assert!(help.contains("AOC_DAY05_INPUT"));
+    assert!(stdout(&["trace", "--help"]).contains("AOC_DAY05_INPUT"));
+}
+
+#[test]
+fn test_all_flag() {
+    let all = stdout(&["--all", "tests/data/example.txt"]);
+    assert_eq!(
+        "range 79+14 -> min location 46\nrange 55+13 -> min location 56\nMinimum location for seeds: 46\n",
+        all
+    );
+
+    // Every range on its own reaches its minimum, and the lowest of them is the overall minimum.
+    let lines: Vec<&str> = all.lines().collect();
+    let mut minima = Vec::new();
+    for line in &lines[..lines.len() - 1] {
+        let (range, minimum) = line.strip_prefix("range ").unwrap().split_once(" -> min location ").unwrap();
+        let solved = stdout(&["--seed-range", &range.replace('+', ":"), "tests/data/example.txt"]);
+        assert!(solved.starts_with(&format!("Minimum location for seeds: {}\n", minimum)), "{}", solved);
+        minima.push(minimum.parse::<u64>().unwrap());
+    }
+    let overall = stdout(&["tests/data/example.txt"]);
+    assert_eq!(overall.lines().next(), lines.last().copied());
+    assert_eq!(Some(&46), minima.iter().min());
+
+    let output = run(&["--all", "--part", "1", "tests/data/example.txt"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: --all requires --part 2, "));
+}
+
+#[test]
+fn test_range_flag() {
+    assert_eq!(
+        stdout(&["tests/data/example.txt"]),
+        stdout(&["--range", "79:14", "--range=55..68", "tests/data/example.txt"])
+    );
+    assert_eq!(
+        "Minimum location for seeds: 1000\nReached from seed 1000 (input range starting at 1000)\n",
+        stdout(&["--range", "1000..2000", "tests/data/example.txt"])
+    );
+    // Overlapping ranges are merged into the one the winning seed is reported in.
+    assert_eq!(
+        "Minimum location for seeds: 46\nReached from seed 82 (input range starting at 78)\n",
+        stdout(&["--range", "80:3", "--range", "78..81", "tests/data/example.txt"])
+    );
+
+    for (range, reason) in [
+        ("79", "expected START:LEN or START..END"),
+        ("5..x", "expected START:LEN or START..END"),
+        ("5..", "expected START:LEN or START..END"),
+        ("9..3", "END is before START"),
+        ("18446744073709551610:10", "START + LEN must fit in 64 bits"),
+    ] {
+        let output = run(&["--range", range, "tests/data/example.txt"]);
+        assert_eq!(Some(2), output.status.code(), "{}", range);
+        let expected = format!("error: invalid range `{}` for --range, {}\nusage: ", range, reason);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with(&expected), "{}", range);
+    }
+    assert_eq!(Some(1), run(&["--range", "5:0", "tests/data/example.txt"]).status.code());
+    assert_eq!(Some(2), run(&["--range", "5:1", "--part", "1", "tests/data/example.txt"]).status.code());
+    assert_eq!(Some(2), run(&["--range", "5:1", "--seed-range", "5:1", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_emit_dot_flag() {
+    let path = format!("{}/example.dot", env!("CARGO_TARGET_TMPDIR"));
+    assert_eq!(stdout(&["tests/data/example.txt"]), stdout(&["--emit-dot", &path, "tests/data/example.txt"]));
+    let dot = std::fs::read_to_string(&path).unwrap();
+    assert!(dot.starts_with("digraph almanac {\n    \"seed\";\n"));
+    assert!(dot.contains("    \"seed\" -> \"soil\" [label=\"2 rules, 50 covered\"];\n"));
+
+    let output = run(&["--emit-dot", "/nonexistent/example.dot", "tests/data/example.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: could not write '/nonexistent/example.dot': "));
+}
+
+#[test]
+fn test_emit_composed_flag() {
+    let example = Almanac::parse(&std::fs::read_to_string("tests/data/example.txt").unwrap()).unwrap();
+    for (name, extra) in [("composed.txt", None), ("composed_identity.txt", Some("--include-identity"))] {
+        let path = format!("{}/{}", env!("CARGO_TARGET_TMPDIR"), name);
+        let args: Vec<&str> =
+            ["--emit-composed", &path].into_iter().chain(extra).chain(["tests/data/example.txt"]).collect();
+        assert_eq!(stdout(&["tests/data/example.txt"]), stdout(&args));
+
+        let composed = Almanac::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(example.seeds, composed.seeds);
+        assert_eq!(1, composed.chain.len());
+        assert_eq!((Some("seed"), "location"), (composed.chain.source(0), composed.chain.range_maps()[0].0.as_str()));
+        let range_map = &composed.chain.range_maps()[0].1;
+        assert_eq!(Ok(()), range_map.validate());
+        assert_eq!(extra.is_some(), range_map.iter().any(|&(destination, source, _)| destination == source));
+        for seed in example.seeds.iter().copied().chain(0..120) {
+            assert_eq!(example.chain.resolve(seed, "location"), composed.chain.resolve(seed, "location"), "{}", seed);
+        }
+        assert_eq!(stdout(&["tests/data/example.txt"]), stdout(&[&path]));
+    }
+
+    let output = run(&["--include-identity", "tests/data/example.txt"]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: --include-identity requires --emit-composed\n"));
+}
+
+#[test]
+fn test_stage_flag() {
+    assert_eq!(
+        "Minimum temperature for seeds: 45\nReached from seed 82 (input range starting at 79)\n",
+        stdout(&["--stage", "temperature", "tests/data/example.txt"])
+    );
+    assert_eq!(
+        "Minimum humidity for seeds: 35\nReached from seed 13\n",
+        stdout(&["--part", "1", "--stage=humidity", "tests/data/example.txt"])
+    );
+    assert_eq!(stdout(&["tests/data/example.txt"]), stdout(&["--stage", "location", "tests/data/example.txt"]));
+    assert_eq!(
+        stdout(&["--stage", "water", "tests/data/example.txt"]),
+        stdout(&["--stage", "water", "--algorithm", "brute-force", "--quiet", "tests/data/example.txt"])
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&["--stage", "light", "--output", "json", "tests/data/example.txt"])).unwrap();
+    assert_eq!("light", report["stage"]);
+
+    let output = run(&["--stage", "seed", "tests/data/example.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "error: unknown stage 'seed', available: soil, fertilizer, water, light, temperature, humidity, location\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn test_queries_flag() {
+    let output = run(&["--queries", "tests/data/queries.txt", "tests/data/example.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("82\n82\n5\n46\n82\n", String::from_utf8(output.stdout).unwrap());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let errors: Vec<&str> = stderr.lines().collect();
+    assert_eq!(3, errors.len(), "{}", stderr);
+    assert_eq!("error: tests/data/queries.txt line 6: invalid number `x`", errors[0]);
+    assert!(errors[1].starts_with("error: tests/data/queries.txt line 7: unknown stage label 'bogus'"));
+    assert!(errors[2].starts_with("error: tests/data/queries.txt line 8: "));
+
+    let queries = format!("{}/queries.txt", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&queries, "79\n14\nsoil 81\n").unwrap();
+    assert_eq!("82\n43\n82\n", stdout(&["--queries", &queries, "tests/data/example.txt"]));
+    assert_eq!("74\n42\n74\n", stdout(&["--queries", &queries, "--stage", "light", "tests/data/example.txt"]));
+
+    assert_eq!(Some(1), run(&["--queries", "tests/data/missing.txt", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_repl_flag() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = command()
+        .args(["--repl", "tests/data/example.txt"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let commands = "seed 79\ntrace 13\nreverse 46\nmin\nstage water 81\n\nsoil 3\nseed x\nquit\nseed 1\n";
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        "> location 82\n\
+         > seed 13 -> soil 13 -> fertilizer 52 -> water 41 -> light 34 -> temperature 34 -> humidity 35 -> location 35\n\
+         > seed 82 -> location 46\n\
+         > location 46\n\
+         > water 83\n\
+         > > error: unknown command `soil 3`, try `help`\n\
+         > error: invalid number `x`\n\
+         > \n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    assert_eq!(Some(2), run(&["--repl"]).status.code());
+}
+
+#[test]
+fn test_kth_flag() {
+    assert_eq!("Lowest location #1 for seeds: 46\n", stdout(&["--kth", "1", "tests/data/example.txt"]));
+    assert_eq!("Lowest location #27 for seeds: 98\n", stdout(&["--kth=27", "tests/data/example.txt"]));
+    assert_eq!("Lowest location #4 for seeds: 86\n", stdout(&["--part", "1", "--kth", "4", "tests/data/example.txt"]));
+    assert_eq!("Lowest location #20 for seeds: 107\n", stdout(&["--kth", "20", "tests/data/non_injective.txt"]));
+    assert_eq!(
+        "Lowest location #20 for seeds: 109\n",
+        stdout(&["--kth", "20", "--distinct", "tests/data/non_injective.txt"])
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&stdout(&["--kth", "3", "--output", "json", "tests/data/example.txt"])).unwrap();
+    assert_eq!(48, report["location"]);
+    assert_eq!(false, report["distinct"]);
+
+    assert_eq!(Some(1), run(&["--kth", "28", "tests/data/example.txt"]).status.code());
+    assert_eq!(Some(2), run(&["--kth", "0", "tests/data/example.txt"]).status.code());
+    assert_eq!(Some(2), run(&["--distinct", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_count_locations_flag() {
+    assert_eq!("Distinct locations for seeds: 27\n", stdout(&["--count-locations", "tests/data/example.txt"]));
+    assert_eq!("Distinct locations for seeds: 4\n", stdout(&["--count-locations", "--part", "1", "tests/data/example.txt"]));
+    assert_eq!("Distinct locations for seeds: 25\n", stdout(&["--count-locations", "tests/data/non_injective.txt"]));
+    assert_eq!(
+        "Distinct temperatures for seeds: 27\n",
+        stdout(&["--count-locations", "--stage", "temperature", "tests/data/example.txt"])
+    );
+}
+
+#[test]
+fn test_histogram_flag() {
+    assert_eq!("40 4\n50 10\n60 1\n80 7\n90 5\n", stdout(&["--histogram", "10", "tests/data/example.txt"]));
+    assert_eq!("30 1\n40 1\n80 2\n", stdout(&["--histogram=10", "--part", "1", "tests/data/example.txt"]));
+    assert_eq!("0 27\n", stdout(&["--histogram", "1000", "tests/data/example.txt"]));
+    assert_eq!(Some(2), run(&["--histogram", "0", "tests/data/example.txt"]).status.code());
+}
+
+#[test]
+fn test_parse_error_underlines_token() {
+    let path = format!("{}/bad_rule.txt", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, "seeds: 1 2\n\na-to-b map:\n10 zero 5\n").unwrap();
+    let output = run(&[&path]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!(
+        "error: line 4: invalid number `zero`\n  10 zero 5\n     ^^^^\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn test_missing_seeds() {
+    let path = format!("{}/no_seeds.txt", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, "seeds:\n\nseed-to-soil map:\n50 98 2\n").unwrap();
+    let output = run(&[&path]);
+    assert_eq!(Some(1), output.status.code());
+    assert_eq!("error: line 1: no seeds found\n", String::from_utf8(output.stderr).unwrap());
+
+    std::fs::write(&path, "seed-to-soil map:\n50 98 2\n").unwrap();
+    let output = run(&["--part", "1", &path]);
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!("error: line 1: expected a `seeds:` line before the first section\n", stderr);
+}
+
+#[test]
+fn test_duplicate_sections() {
+    let output = run(&["tests/data/duplicate_section.txt"]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).starts_with("error: line 11: section already started on line 3\n"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_eq!(
+        "Minimum location for seeds: 46\nReached from seed 82 (input range starting at 79)\n",
+        stdout(&["--merge-duplicate-sections", "tests/data/duplicate_section.txt"])
+    );
+    assert_eq!(
+        "Minimum location for seeds: 35\nReached from seed 13\n",
+        stdout(&["--merge-duplicate-sections", "--part", "1", "tests/data/duplicate_section.txt"])
+    );
+}
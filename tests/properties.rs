@@ -0,0 +1,121 @@
+use aoc2023_day05::{RangeMap, RangeMapChain};
+use proptest::prelude::*;
+
+/// Rules laid out left to right with random gaps, so no two source intervals overlap.
+fn range_map() -> impl Strategy<Value = RangeMap> {
+    prop::collection::vec((0u64..20, 1u64..30, 0u64..300), 0..8).prop_map(|rules| {
+        let mut source = 0;
+        RangeMap::new(
+            rules
+                .into_iter()
+                .map(|(gap, size, destination)| {
+                    source += gap;
+                    let rule = (destination, source, size);
+                    source += size;
+                    rule
+                })
+                .collect()
+        )
+    })
+}
+
+fn chain() -> impl Strategy<Value = RangeMapChain> {
+    prop::collection::vec(range_map(), 1..5).prop_map(|range_maps| {
+        RangeMapChain::new(
+            range_maps
+                .into_iter()
+                .enumerate()
+                .map(|(index, range_map)| (format!("stage{}", index), range_map))
+                .collect()
+        )
+        .unwrap()
+    })
+}
+
+proptest! {
+    #[test]
+    fn resolve_and_resolve_ranges_agree(
+        chain in chain(),
+        seeds in prop::collection::vec((0u64..300, 1u64..60), 1..4),
+    ) {
+        let label = chain.range_maps().last().unwrap().0.clone();
+        let resolved = chain.resolve_ranges(&seeds, &label).unwrap();
+
+        for seed in seeds.iter().flat_map(|&(start, size)| start..start + size) {
+            let location = chain.resolve(seed, &label).unwrap();
+            let containing = resolved
+                .iter()
+                .filter(|&&(start, size)| start <= location && location < start + size)
+                .count();
+            prop_assert_eq!(1, containing, "seed {} resolved to {} outside {:?}", seed, location, resolved);
+        }
+
+        let brute_force = seeds
+            .iter()
+            .flat_map(|&(start, size)| start..start + size)
+            .map(|seed| chain.resolve(seed, &label).unwrap())
+            .min();
+        prop_assert_eq!(brute_force, resolved.iter().map(|&(start, _)| start).min());
+    }
+
+    #[test]
+    fn get_and_get_ranges_agree(range_map in range_map(), start in 0u64..300, size in 1u64..60) {
+        let mut expected: Vec<u64> = (start..start + size).map(|value| range_map.get_or_identity(value)).collect();
+        let mut actual: Vec<u64> = range_map
+            .get_ranges(start, size)
+            .into_iter()
+            .flat_map(|(output, output_size)| output..output + output_size)
+            .collect();
+        expected.sort();
+        actual.sort();
+        prop_assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn get_and_get_many_agree(
+        rules in prop::collection::vec((0u64..300, 0u64..200, 0u64..40), 0..10),
+        values in prop::collection::vec(0u64..260, 0..100),
+    ) {
+        // Rules may overlap or be empty, and `get_many` must still follow the overlap policy of `get`.
+        let range_map = RangeMap::new(rules);
+        let expected: Vec<Option<u64>> = values.iter().map(|&value| range_map.get(value)).collect();
+        prop_assert_eq!(expected, range_map.get_many(&values));
+    }
+
+    #[test]
+    fn resolve_and_resolve_many_agree(chain in chain(), values in prop::collection::vec(0u64..400, 0..60)) {
+        for (index, (label, _)) in chain.range_maps().iter().enumerate() {
+            let expected: Vec<u64> = values.iter().map(|&value| chain.resolve_at(value, index + 1)).collect();
+            prop_assert_eq!(expected, chain.resolve_many(&values, label).unwrap());
+        }
+    }
+
+    #[test]
+    fn map_ranges_and_get_agree(range_map in range_map(), start in 0u64..300, size in 0u64..60) {
+        let mapped: Vec<(u64, u64)> = range_map.map_ranges(start, size).collect();
+        prop_assert!(mapped.windows(2).all(|pair| pair[0] <= pair[1]));
+        let mut values: Vec<u64> = mapped.iter().flat_map(|&(start, size)| start..start + size).collect();
+        values.sort_unstable();
+        let mut expected: Vec<u64> = (start..start + size)
+            .map(|value| {
+                range_map
+                    .ranges()
+                    .iter()
+                    .find(|&&(_, source, size)| source <= value && value < source + size)
+                    .map_or(value, |&(destination, source, _)| destination + (value - source))
+            })
+            .collect();
+        expected.sort_unstable();
+        prop_assert_eq!(expected, values);
+    }
+
+    #[test]
+    fn min_location_and_resolve_ranges_agree(
+        chain in chain(),
+        seeds in prop::collection::vec((0u64..300, 0u64..60), 0..4),
+    ) {
+        let label = chain.range_maps().last().unwrap().0.clone();
+        let resolved = chain.resolve_ranges(&seeds, &label).unwrap();
+        prop_assert_eq!(resolved.first().map(|&(start, _)| start), chain.min_location(&seeds));
+    }
+}
@@ -0,0 +1,34 @@
+import pathlib
+
+import pytest
+
+from aoc2023_day05 import Almanac
+
+EXAMPLE = (pathlib.Path(__file__).parent.parent / "data" / "example.txt").read_text()
+
+
+def test_min_location():
+    almanac = Almanac.parse(EXAMPLE)
+    assert almanac.min_location(1) == 35
+    assert almanac.min_location(2) == 46
+    with pytest.raises(ValueError, match="invalid part `3`"):
+        almanac.min_location(3)
+
+
+def test_resolve_and_trace():
+    almanac = Almanac.parse(EXAMPLE)
+    assert almanac.resolve(79) == 82
+    assert almanac.trace(79) == [
+        ("soil", 81),
+        ("fertilizer", 81),
+        ("water", 81),
+        ("light", 74),
+        ("temperature", 78),
+        ("humidity", 78),
+        ("location", 82),
+    ]
+
+
+def test_parse_error():
+    with pytest.raises(ValueError, match=r"^line 8: invalid number `3y`$"):
+        Almanac.parse((pathlib.Path(__file__).parent.parent / "data" / "malformed.txt").read_text())